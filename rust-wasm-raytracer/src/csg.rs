@@ -0,0 +1,186 @@
+use crate::aabb::Aabb;
+use crate::geometric_objects::GeometricObject;
+use crate::shade_record::ShadeRecord;
+use crate::Ray;
+
+#[derive(Clone, Copy)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOperation {
+    #[inline]
+    fn inside(&self, inside_left: bool, inside_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => inside_left || inside_right,
+            CsgOperation::Intersection => inside_left && inside_right,
+            CsgOperation::Difference => inside_left && !inside_right,
+        }
+    }
+}
+
+// Combines two closed objects. Surfaces that survive the operation keep the material of the
+// object they belong to, surfaces of the subtracted object have their normal flipped.
+pub struct Csg {
+    operation: CsgOperation,
+    left: Box<dyn GeometricObject>,
+    right: Box<dyn GeometricObject>,
+}
+
+impl Csg {
+    pub fn new(
+        operation: CsgOperation,
+        left: Box<dyn GeometricObject>,
+        right: Box<dyn GeometricObject>,
+    ) -> Self {
+        Csg {
+            operation,
+            left,
+            right,
+        }
+    }
+}
+
+impl GeometricObject for Csg {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        self.bounding_box().hit(ray, t_min, t_max)?;
+
+        self.hits_along_ray(ray)
+            .into_iter()
+            .find(|rec| rec.intersect_parameter > t_min && rec.intersect_parameter < t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self.operation {
+            CsgOperation::Union => self
+                .left
+                .bounding_box()
+                .surrounding(&self.right.bounding_box()),
+            CsgOperation::Intersection | CsgOperation::Difference => self.left.bounding_box(),
+        }
+    }
+
+    fn hits_along_ray(&self, ray: &Ray) -> Vec<ShadeRecord<'_>> {
+        let mut left_hits = self.left.hits_along_ray(ray).into_iter().peekable();
+        let mut right_hits = self.right.hits_along_ray(ray).into_iter().peekable();
+        let (mut inside_left, mut inside_right) = (false, false);
+        let mut hits = Vec::new();
+
+        loop {
+            let take_left = match (left_hits.peek(), right_hits.peek()) {
+                (Some(l), Some(r)) => l.intersect_parameter <= r.intersect_parameter,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+
+            let was_inside = self.operation.inside(inside_left, inside_right);
+            let mut rec = if take_left {
+                inside_left = !inside_left;
+                left_hits.next()
+            } else {
+                inside_right = !inside_right;
+                right_hits.next()
+            }
+            .expect("peeked crossing is present");
+
+            if was_inside != self.operation.inside(inside_left, inside_right) {
+                if !take_left {
+                    if let CsgOperation::Difference = self.operation {
+                        rec.normal = -rec.normal;
+                        rec.geometric_normal = -rec.geometric_normal;
+                    }
+                }
+                hits.push(rec);
+            }
+        }
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometric_objects::Sphere;
+    use crate::materials::{Material, Texture};
+    use cgmath::{vec3, InnerSpace, Point3};
+
+    // Unit spheres at x = -0.5 and x = 0.5, the ray along the x axis crosses them at -1.5, 0.5
+    // and -0.5, 1.5.
+    fn csg(operation: CsgOperation) -> Csg {
+        let sphere = |x| {
+            Box::new(Sphere::new(
+                Point3::new(x, 0.0, 0.0),
+                1.0,
+                Material::Lambertian {
+                    texture: Texture::scalar(0.5),
+                },
+            ))
+        };
+        Csg::new(operation, sphere(-0.5), sphere(0.5))
+    }
+
+    fn ray() -> Ray {
+        Ray::new(Point3::new(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), 0.0)
+    }
+
+    // Points along the x axis where the ray crosses the surface of the combined object, with
+    // the x component of the normal there.
+    fn crossings(csg: &Csg) -> Vec<(f32, f32)> {
+        csg.hits_along_ray(&ray())
+            .iter()
+            .map(|rec| (rec.local_hit_point.x, rec.normal.x))
+            .collect()
+    }
+
+    fn assert_crossings(actual: &[(f32, f32)], expected: &[(f32, f32)]) {
+        assert_eq!(actual.len(), expected.len(), "crossings {:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a.0 - e.0).abs() < 1e-4 && (a.1 - e.1).abs() < 1e-4,
+                "crossings {:?}, expected {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn union_keeps_the_outer_surfaces() {
+        let union = csg(CsgOperation::Union);
+        assert_crossings(&crossings(&union), &[(-1.5, -1.0), (1.5, 1.0)]);
+    }
+
+    #[test]
+    fn intersection_keeps_the_overlap() {
+        let intersection = csg(CsgOperation::Intersection);
+        assert_crossings(&crossings(&intersection), &[(-0.5, -1.0), (0.5, 1.0)]);
+    }
+
+    #[test]
+    fn difference_flips_the_normals_of_the_subtracted_object() {
+        let difference = csg(CsgOperation::Difference);
+        assert_crossings(&crossings(&difference), &[(-1.5, -1.0), (-0.5, 1.0)]);
+    }
+
+    #[test]
+    fn hit_returns_the_first_crossing_in_range() {
+        let difference = csg(CsgOperation::Difference);
+        let rec = difference
+            .hit(&ray(), 4.0, f32::MAX)
+            .expect("the ray leaves the object");
+        assert!((rec.intersect_parameter - 4.5).abs() < 1e-4);
+        assert!((rec.normal - vec3(1.0, 0.0, 0.0)).magnitude() < 1e-4);
+        assert!(difference.hit(&ray(), 5.0, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn bounding_box_follows_the_operation() {
+        let union = csg(CsgOperation::Union).bounding_box();
+        let difference = csg(CsgOperation::Difference).bounding_box();
+        assert!((union.min.x + 1.5).abs() < 1e-6 && (union.max.x - 1.5).abs() < 1e-6);
+        assert!((difference.min.x + 1.5).abs() < 1e-6 && (difference.max.x - 0.5).abs() < 1e-6);
+    }
+}
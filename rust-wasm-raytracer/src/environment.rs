@@ -0,0 +1,173 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+use rand::random;
+use std::f32;
+
+use crate::image::Image;
+
+// Piecewise constant distribution over the pixels of an image, rows are sampled from the
+// marginal distribution and columns from the conditional distribution of the chosen row.
+struct Distribution2D {
+    width: usize,
+    height: usize,
+    weights: Vec<f32>,
+    conditional_cdfs: Vec<Vec<f32>>,
+    marginal_cdf: Vec<f32>,
+    total: f32,
+}
+
+fn cumulative(values: impl Iterator<Item = f32>) -> Vec<f32> {
+    let mut acc = 0.0;
+    values
+        .map(|value| {
+            acc += value;
+            acc
+        })
+        .collect()
+}
+
+// Index of the first cdf entry above `target`, together with the offset inside that entry.
+fn sample_cdf(cdf: &[f32], target: f32) -> (usize, f32) {
+    let index = cdf.partition_point(|&c| c <= target).min(cdf.len() - 1);
+    let start = if index == 0 { 0.0 } else { cdf[index - 1] };
+    let width = cdf[index] - start;
+    let offset = if width > 0.0 {
+        ((target - start) / width).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    (index, offset)
+}
+
+impl Distribution2D {
+    fn new(width: usize, height: usize, weights: Vec<f32>) -> Self {
+        let conditional_cdfs: Vec<Vec<f32>> = weights
+            .chunks(width)
+            .map(|row| cumulative(row.iter().cloned()))
+            .collect();
+        let marginal_cdf = cumulative(conditional_cdfs.iter().map(|cdf| cdf[width - 1]));
+        let total = marginal_cdf[height - 1];
+
+        Distribution2D {
+            width,
+            height,
+            weights,
+            conditional_cdfs,
+            marginal_cdf,
+            total,
+        }
+    }
+
+    // Returns continuous (u, v) in [0, 1]^2 and the density with respect to the unit square.
+    fn sample(&self) -> (f32, f32, f32) {
+        let (row, dv) = sample_cdf(&self.marginal_cdf, random::<f32>() * self.total);
+        let row_cdf = &self.conditional_cdfs[row];
+        let (column, du) = sample_cdf(row_cdf, random::<f32>() * row_cdf[self.width - 1]);
+
+        (
+            (column as f32 + du) / self.width as f32,
+            (row as f32 + dv) / self.height as f32,
+            self.pdf(column, row),
+        )
+    }
+
+    #[inline]
+    fn pdf(&self, column: usize, row: usize) -> f32 {
+        if self.total > 0.0 {
+            self.weights[column + row * self.width] * (self.width * self.height) as f32 / self.total
+        } else {
+            0.0
+        }
+    }
+}
+
+// Equirectangular environment map, the top row of the image is straight up (+y).
+// `rotation` turns the map around the y axis, in degrees.
+pub struct EnvironmentLight {
+    image: Image,
+    rotation: f32,
+    distribution: Distribution2D,
+}
+
+#[inline]
+fn luminance(color: &Vector3<f32>) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+impl EnvironmentLight {
+    pub fn new(image: Image, rotation: f32) -> Self {
+        let (width, height) = (image.width(), image.height());
+
+        // Rows near the poles cover less solid angle, the sine term accounts for that.
+        let weights = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let sin_theta = (f32::consts::PI * (y as f32 + 0.5) / height as f32).sin();
+                luminance(&image.pixel(x, y).truncate()) * sin_theta
+            })
+            .collect();
+
+        EnvironmentLight {
+            image,
+            rotation: rotation.to_radians(),
+            distribution: Distribution2D::new(width, height, weights),
+        }
+    }
+
+    #[inline]
+    fn direction_to_uv(&self, direction: &Vector3<f32>) -> (f32, f32) {
+        let d = direction.normalize();
+        let phi = d.z.atan2(d.x) - self.rotation;
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        (
+            (phi / (2.0 * f32::consts::PI)).rem_euclid(1.0),
+            theta / f32::consts::PI,
+        )
+    }
+
+    #[inline]
+    fn pixel_at(&self, u: f32, v: f32) -> (usize, usize) {
+        let width = self.image.width();
+        let height = self.image.height();
+        (
+            ((u * width as f32) as usize).min(width - 1),
+            ((v * height as f32) as usize).min(height - 1),
+        )
+    }
+
+    // Radiance arriving from `direction`, looked up without filtering so it matches the
+    // piecewise constant sampling density.
+    pub fn radiance(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let (u, v) = self.direction_to_uv(direction);
+        let (x, y) = self.pixel_at(u, v);
+        self.image.pixel(x, y).truncate()
+    }
+
+    // Importance samples a direction by luminance, returns it with its radiance and its
+    // density with respect to solid angle.
+    pub fn sample(&self) -> (Vector3<f32>, Vector3<f32>, f32) {
+        let (u, v, uv_pdf) = self.distribution.sample();
+        let theta = v * f32::consts::PI;
+        let phi = u * 2.0 * f32::consts::PI + self.rotation;
+        let sin_theta = theta.sin();
+        let direction = vec3(sin_theta * phi.cos(), theta.cos(), sin_theta * phi.sin());
+
+        let pdf = if sin_theta > 0.0 {
+            uv_pdf / (2.0 * f32::consts::PI * f32::consts::PI * sin_theta)
+        } else {
+            0.0
+        };
+        let (x, y) = self.pixel_at(u, v);
+        (direction, self.image.pixel(x, y).truncate(), pdf)
+    }
+
+    pub fn pdf(&self, direction: &Vector3<f32>) -> f32 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * f32::consts::PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = self.pixel_at(u, v);
+        self.distribution.pdf(x, y) / (2.0 * f32::consts::PI * f32::consts::PI * sin_theta)
+    }
+}
@@ -1,16 +1,94 @@
 extern crate cgmath;
 
 use cgmath::prelude::*;
-use cgmath::{Point3, Vector3};
+use cgmath::{vec3, Point3, Vector3};
 use std::f32;
 
+use crate::aabb::Aabb;
 use crate::materials::Material;
 use crate::Ray;
 use crate::shade_record::ShadeRecord;
 
 
 pub trait GeometricObject {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>>;
+    fn bounding_box(&self) -> Aabb;
+
+    // Every surface crossing along the whole line of the ray, ordered by the ray parameter.
+    // For closed objects crossings alternate between entering and leaving the object.
+    fn hits_along_ray(&self, ray: &Ray) -> Vec<ShadeRecord<'_>> {
+        let mut hits = Vec::new();
+        let mut t_min = -f32::MAX;
+
+        while let Some(rec) = self.hit(ray, t_min, f32::MAX) {
+            t_min = rec.intersect_parameter + 1e-4 * rec.intersect_parameter.abs().max(1.0);
+            hits.push(rec);
+        }
+        hits
+    }
+}
+
+// Spherical coordinates of a point on the unit sphere, u around the y axis and v from the bottom pole.
+#[inline]
+pub(crate) fn spherical_uv(p: &Vector3<f32>) -> (f32, f32) {
+    let phi = f32::atan2(-p.z, p.x) + f32::consts::PI;
+    let theta = f32::acos((-p.y).clamp(-1.0, 1.0));
+    (phi / (2.0 * f32::consts::PI), theta / f32::consts::PI)
+}
+
+// Direction in which the angle of `spherical_uv` and `polar_uv` increases, `offset` is
+// measured from the y axis through the center.
+#[inline]
+pub(crate) fn azimuthal_tangent(offset: &Vector3<f32>) -> Vector3<f32> {
+    let tangent = vec3(offset.z, 0.0, -offset.x);
+    if tangent.magnitude2() > 1e-12 {
+        tangent.normalize()
+    } else {
+        vec3(1.0, 0.0, 0.0)
+    }
+}
+
+// Direction away from the y axis through the center, in which the polar v coordinate increases.
+#[inline]
+fn radial_direction(offset: &Vector3<f32>) -> Vector3<f32> {
+    let radial = vec3(offset.x, 0.0, offset.z);
+    if radial.magnitude2() > 1e-12 {
+        radial.normalize()
+    } else {
+        vec3(0.0, 0.0, 1.0)
+    }
+}
+
+// Polar coordinates around the y axis, u is the angle and v the distance relative to `radius`.
+#[inline]
+fn polar_uv(dx: f32, dz: f32, radius: f32) -> (f32, f32) {
+    let phi = f32::atan2(-dz, dx) + f32::consts::PI;
+    (
+        phi / (2.0 * f32::consts::PI),
+        (dx * dx + dz * dz).sqrt() / radius,
+    )
+}
+
+#[inline]
+fn orthonormal_basis(w: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let helper = if w.x.abs() > 0.9 {
+        vec3(0.0, 1.0, 0.0)
+    } else {
+        vec3(1.0, 0.0, 0.0)
+    };
+    let u = helper.cross(*w).normalize();
+    (u, w.cross(u))
+}
+
+// Both roots of a*t^2 + b*t + c, near one first.
+fn solve_quadratic(a: f32, b: f32, c: f32) -> Option<(f32, f32)> {
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 || a == 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let (t0, t1) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+    Some((t0.min(t1), t0.max(t1)))
 }
 
 pub struct Sphere {
@@ -30,7 +108,7 @@ impl Sphere {
 }
 
 impl GeometricObject for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
         let oc: Vector3<f32> = ray.origin - self.center;
         let a = ray.direction.magnitude2();
         let b = oc.dot(ray.direction);
@@ -52,18 +130,31 @@ impl GeometricObject for Sphere {
             option_t.and_then(|intersect_parameter| {
                 let local_hit_point = ray.point_at_parameter(intersect_parameter);
                 let normal = (local_hit_point - self.center) / self.radius;
+                let (u, v) = spherical_uv(&normal);
+                let tangent = azimuthal_tangent(&normal);
 
                 Some(ShadeRecord {
                     intersect_parameter,
                     local_hit_point,
+                    object_hit_point: Point3::from_vec(local_hit_point - self.center),
                     normal,
+                    geometric_normal: normal,
+                    tangent,
+                    bitangent: normal.cross(tangent),
                     material: &self.material,
+                    u,
+                    v,
                 })
             })
         } else {
             None
         }
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = vec3(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - extent, self.center + extent)
+    }
 }
 
 pub struct MovingSphere {
@@ -102,7 +193,7 @@ impl MovingSphere {
 }
 
 impl GeometricObject for MovingSphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
         let oc: Vector3<f32> = ray.origin - self.center(ray.time);
         let a = ray.direction.magnitude2();
         let b = oc.dot(ray.direction);
@@ -123,19 +214,35 @@ impl GeometricObject for MovingSphere {
 
             option_t.and_then(|intersect_parameter| {
                 let local_hit_point = ray.point_at_parameter(intersect_parameter);
-                let normal = (local_hit_point - self.center(ray.time)) / self.radius;
+                let center = self.center(ray.time);
+                let normal = (local_hit_point - center) / self.radius;
+                let (u, v) = spherical_uv(&normal);
+                let tangent = azimuthal_tangent(&normal);
 
                 Some(ShadeRecord {
                     intersect_parameter,
                     local_hit_point,
+                    object_hit_point: Point3::from_vec(local_hit_point - center),
                     normal,
+                    geometric_normal: normal,
+                    tangent,
+                    bitangent: normal.cross(tangent),
                     material: &self.material,
+                    u,
+                    v,
                 })
             })
         } else {
             None
         }
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = vec3(self.radius, self.radius, self.radius);
+        let start = Aabb::new(self.center_start - extent, self.center_start + extent);
+        let end = Aabb::new(self.center_end - extent, self.center_end + extent);
+        start.surrounding(&end)
+    }
 }
 
 pub struct Rect {
@@ -154,7 +261,7 @@ pub const fn new(x0: f32, x1: f32, z0: f32, z1: f32, y_height: f32, material: Ma
 }
 
 impl GeometricObject for Rect {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
         let intersect_param = match (self.y_height - ray.origin.y) / ray.direction.y {
             t if t < t_min || t > t_max => None,
             t => Some(t)
@@ -163,14 +270,544 @@ impl GeometricObject for Rect {
         intersect_param.and_then(|t| {
             match (ray.origin.x + t * ray.direction.x , ray.origin.z + t * ray.direction.z) {
                 (x, z)  if x < self.x0 || x > self.x1 || z < self.z0 || z > self.z1 => None,
-                _ =>  
+                (x, z) =>
                     Some(ShadeRecord{
                         intersect_parameter: t,
                         local_hit_point: ray.point_at_parameter(t),
-                        normal: Vector3::new(0.0, 0.0, 1.0),
+                        object_hit_point: Point3::new(x - self.x0, 0.0, z - self.z0),
+                        normal: Vector3::new(0.0, 1.0, 0.0),
+                        geometric_normal: Vector3::new(0.0, 1.0, 0.0),
+                        tangent: Vector3::new(1.0, 0.0, 0.0),
+                        bitangent: Vector3::new(0.0, 0.0, 1.0),
                         material: &self.material,
+                        u: (x - self.x0) / (self.x1 - self.x0),
+                        v: (z - self.z0) / (self.z1 - self.z0),
                     })
             }
         })
     }
-}
\ No newline at end of file
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            Point3::new(self.x0, self.y_height - 0.0001, self.z0),
+            Point3::new(self.x1, self.y_height + 0.0001, self.z1),
+        )
+    }
+}
+
+pub struct Disk {
+    center: Point3<f32>,
+    normal: Vector3<f32>,
+    radius: f32,
+    material: Material,
+}
+
+impl Disk {
+    pub fn new(center: Point3<f32>, normal: Vector3<f32>, radius: f32, material: Material) -> Self {
+        Disk {
+            center,
+            normal: normal.normalize(),
+            radius,
+            material,
+        }
+    }
+}
+
+impl GeometricObject for Disk {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        match (self.center - ray.origin).dot(self.normal) / denominator {
+            t if t < t_min || t > t_max => None,
+            t => {
+                let local_hit_point = ray.point_at_parameter(t);
+                let offset = local_hit_point - self.center;
+                if offset.magnitude2() > self.radius * self.radius {
+                    return None;
+                }
+                let (tangent, bitangent) = orthonormal_basis(&self.normal);
+                let (dx, dz) = (offset.dot(tangent), offset.dot(bitangent));
+                let (u, v) = polar_uv(dx, dz, self.radius);
+                let (angular, radial) = if dx * dx + dz * dz > 1e-12 {
+                    (
+                        (tangent * dz - bitangent * dx).normalize(),
+                        (tangent * dx + bitangent * dz).normalize(),
+                    )
+                } else {
+                    (tangent, bitangent)
+                };
+
+                Some(ShadeRecord {
+                    intersect_parameter: t,
+                    local_hit_point,
+                    object_hit_point: Point3::from_vec(offset),
+                    normal: self.normal,
+                    geometric_normal: self.normal,
+                    tangent: angular,
+                    bitangent: radial,
+                    material: &self.material,
+                    u,
+                    v,
+                })
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let n = self.normal;
+        let extent = vec3(
+            self.radius * (1.0 - n.x * n.x).max(0.0).sqrt() + 0.0001,
+            self.radius * (1.0 - n.y * n.y).max(0.0).sqrt() + 0.0001,
+            self.radius * (1.0 - n.z * n.z).max(0.0).sqrt() + 0.0001,
+        );
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
+
+// Hit parameter, normal, bitangent and UVs of the closest hit of a cylinder or cone so far.
+type CandidateHit = (f32, Vector3<f32>, Vector3<f32>, f32, f32);
+
+// Caps of cylinders and cones are horizontal disks, returns the hit parameter and polar UVs.
+fn cap_hit(ray: &Ray, center: &Point3<f32>, radius: f32, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+    match (center.y - ray.origin.y) / ray.direction.y {
+        t if !(t > t_min && t < t_max) => None,
+        t => {
+            let p = ray.point_at_parameter(t);
+            let (dx, dz) = (p.x - center.x, p.z - center.z);
+            if dx * dx + dz * dz > radius * radius {
+                None
+            } else {
+                let (u, v) = polar_uv(dx, dz, radius);
+                Some((t, u, v))
+            }
+        }
+    }
+}
+
+// Cylinder aligned with the y axis, `center` is the center of its bottom cap.
+pub struct Cylinder {
+    center: Point3<f32>,
+    radius: f32,
+    height: f32,
+    capped: bool,
+    material: Material,
+}
+
+impl Cylinder {
+    pub const fn new(center: Point3<f32>, radius: f32, height: f32, capped: bool, material: Material) -> Self {
+        Cylinder {
+            center,
+            radius,
+            height,
+            capped,
+            material,
+        }
+    }
+}
+
+impl GeometricObject for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        let (ox, oz) = (ray.origin.x - self.center.x, ray.origin.z - self.center.z);
+        let (dx, dz) = (ray.direction.x, ray.direction.z);
+        let (y0, y1) = (self.center.y, self.center.y + self.height);
+
+        let mut closest: Option<CandidateHit> = None;
+        let mut closest_so_far = t_max;
+
+        if let Some((near, far)) = solve_quadratic(
+            dx * dx + dz * dz,
+            2.0 * (ox * dx + oz * dz),
+            ox * ox + oz * oz - self.radius * self.radius,
+        ) {
+            for t in [near, far] {
+                let y = ray.origin.y + t * ray.direction.y;
+                if t > t_min && t < closest_so_far && y >= y0 && y <= y1 {
+                    let (px, pz) = (ox + t * dx, oz + t * dz);
+                    let (u, _) = polar_uv(px, pz, self.radius);
+                    let v = (y - y0) / self.height;
+                    closest = Some((t, vec3(px, 0.0, pz) / self.radius, vec3(0.0, 1.0, 0.0), u, v));
+                    closest_so_far = t;
+                    break;
+                }
+            }
+        }
+
+        if self.capped {
+            for (y, normal) in [(y0, vec3(0.0, -1.0, 0.0)), (y1, vec3(0.0, 1.0, 0.0))] {
+                let cap_center = Point3::new(self.center.x, y, self.center.z);
+                if let Some((t, u, v)) = cap_hit(ray, &cap_center, self.radius, t_min, closest_so_far) {
+                    let offset = ray.point_at_parameter(t) - cap_center;
+                    closest = Some((t, normal, radial_direction(&offset), u, v));
+                    closest_so_far = t;
+                }
+            }
+        }
+
+        closest.map(|(t, normal, bitangent, u, v)| {
+            let local_hit_point = ray.point_at_parameter(t);
+            ShadeRecord {
+                intersect_parameter: t,
+                local_hit_point,
+                object_hit_point: Point3::from_vec(local_hit_point - self.center),
+                normal,
+                geometric_normal: normal,
+                tangent: azimuthal_tangent(&(local_hit_point - self.center)),
+                bitangent,
+                material: &self.material,
+                u,
+                v,
+            }
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            Point3::new(self.center.x - self.radius, self.center.y, self.center.z - self.radius),
+            Point3::new(
+                self.center.x + self.radius,
+                self.center.y + self.height,
+                self.center.z + self.radius,
+            ),
+        )
+    }
+}
+
+// Cone aligned with the y axis, opening downwards from `apex` to a base of `radius`.
+pub struct Cone {
+    apex: Point3<f32>,
+    radius: f32,
+    height: f32,
+    capped: bool,
+    material: Material,
+}
+
+impl Cone {
+    pub const fn new(apex: Point3<f32>, radius: f32, height: f32, capped: bool, material: Material) -> Self {
+        Cone {
+            apex,
+            radius,
+            height,
+            capped,
+            material,
+        }
+    }
+}
+
+impl GeometricObject for Cone {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        let k2 = (self.radius / self.height) * (self.radius / self.height);
+        let (ox, oy, oz) = (
+            ray.origin.x - self.apex.x,
+            self.apex.y - ray.origin.y,
+            ray.origin.z - self.apex.z,
+        );
+        let (dx, dy, dz) = (ray.direction.x, ray.direction.y, ray.direction.z);
+
+        let mut closest: Option<CandidateHit> = None;
+        let mut closest_so_far = t_max;
+
+        if let Some((near, far)) = solve_quadratic(
+            dx * dx + dz * dz - k2 * dy * dy,
+            2.0 * (ox * dx + oz * dz + k2 * oy * dy),
+            ox * ox + oz * oz - k2 * oy * oy,
+        ) {
+            for t in [near, far] {
+                // Depth below the apex, the quadric also contains the mirrored upper nappe.
+                let depth = oy - t * dy;
+                if t > t_min && t < closest_so_far && depth >= 0.0 && depth <= self.height {
+                    let (px, pz) = (ox + t * dx, oz + t * dz);
+                    let (u, _) = polar_uv(px, pz, self.radius);
+                    let normal = vec3(px, k2 * depth, pz).normalize();
+                    let slant = azimuthal_tangent(&vec3(px, 0.0, pz)).cross(normal);
+                    closest = Some((t, normal, slant, u, depth / self.height));
+                    closest_so_far = t;
+                    break;
+                }
+            }
+        }
+
+        if self.capped {
+            let base_center = Point3::new(self.apex.x, self.apex.y - self.height, self.apex.z);
+            if let Some((t, u, v)) = cap_hit(ray, &base_center, self.radius, t_min, closest_so_far) {
+                let offset = ray.point_at_parameter(t) - base_center;
+                closest = Some((t, vec3(0.0, -1.0, 0.0), radial_direction(&offset), u, v));
+            }
+        }
+
+        closest.map(|(t, normal, bitangent, u, v)| {
+            let local_hit_point = ray.point_at_parameter(t);
+            ShadeRecord {
+                intersect_parameter: t,
+                local_hit_point,
+                object_hit_point: Point3::from_vec(local_hit_point - self.apex),
+                normal,
+                geometric_normal: normal,
+                tangent: azimuthal_tangent(&(local_hit_point - self.apex)),
+                bitangent,
+                material: &self.material,
+                u,
+                v,
+            }
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(
+            Point3::new(
+                self.apex.x - self.radius,
+                self.apex.y - self.height,
+                self.apex.z - self.radius,
+            ),
+            Point3::new(self.apex.x + self.radius, self.apex.y, self.apex.z + self.radius),
+        )
+    }
+}
+
+// Largest real root of x^3 + a*x^2 + b*x + c.
+fn largest_cubic_root(a: f64, b: f64, c: f64) -> f64 {
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+
+    let root = if discriminant > 0.0 {
+        let sqrt_discriminant = discriminant.sqrt();
+        (-q / 2.0 + sqrt_discriminant).cbrt() + (-q / 2.0 - sqrt_discriminant).cbrt()
+    } else {
+        let r = (-p / 3.0).sqrt();
+        let cos_argument = if r > 0.0 {
+            (-q / (2.0 * r * r * r)).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        2.0 * r * (cos_argument.acos() / 3.0).cos()
+    };
+    root - a / 3.0
+}
+
+// Real roots of c[4]*x^4 + c[3]*x^3 + c[2]*x^2 + c[1]*x + c[0], using Ferrari's method
+// followed by a couple of Newton iterations to recover precision lost in the resolvent.
+fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    let (a, b, cc, d) = (c[3] / c[4], c[2] / c[4], c[1] / c[4], c[0] / c[4]);
+
+    // Depressed quartic y^4 + p*y^2 + q*y + r with x = y - a / 4.
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = cc - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * cc / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut roots = Vec::with_capacity(4);
+    let mut push_quadratic_roots = |b: f64, c: f64| {
+        let discriminant = b * b - 4.0 * c;
+        if discriminant >= 0.0 {
+            let sqrt_discriminant = discriminant.sqrt();
+            roots.push((-b - sqrt_discriminant) / 2.0);
+            roots.push((-b + sqrt_discriminant) / 2.0);
+        }
+    };
+
+    if q.abs() < 1e-12 {
+        // Biquadratic, solve for y^2.
+        let discriminant = p * p - 4.0 * r;
+        if discriminant >= 0.0 {
+            for z in [(-p - discriminant.sqrt()) / 2.0, (-p + discriminant.sqrt()) / 2.0] {
+                if z >= 0.0 {
+                    push_quadratic_roots(0.0, -z);
+                }
+            }
+        }
+    } else {
+        let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0).max(1e-12);
+        let s = (2.0 * m).sqrt();
+        push_quadratic_roots(-s, p / 2.0 + m + q / (2.0 * s));
+        push_quadratic_roots(s, p / 2.0 + m - q / (2.0 * s));
+    }
+
+    roots
+        .into_iter()
+        .map(|y| {
+            let mut x = y - a / 4.0;
+            for _ in 0..2 {
+                let value = (((c[4] * x + c[3]) * x + c[2]) * x + c[1]) * x + c[0];
+                let derivative = ((4.0 * c[4] * x + 3.0 * c[3]) * x + 2.0 * c[2]) * x + c[1];
+                if derivative != 0.0 {
+                    x -= value / derivative;
+                }
+            }
+            x
+        })
+        .collect()
+}
+
+// Torus lying in the xz plane around `center`.
+pub struct Torus {
+    center: Point3<f32>,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Material,
+}
+
+impl Torus {
+    pub const fn new(center: Point3<f32>, major_radius: f32, minor_radius: f32, material: Material) -> Self {
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+}
+
+impl GeometricObject for Torus {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        self.bounding_box().hit(ray, t_min, t_max)?;
+
+        let o = ray.origin - self.center;
+        let (ox, oy, oz) = (f64::from(o.x), f64::from(o.y), f64::from(o.z));
+        let (dx, dy, dz) = (
+            f64::from(ray.direction.x),
+            f64::from(ray.direction.y),
+            f64::from(ray.direction.z),
+        );
+        let major2 = f64::from(self.major_radius) * f64::from(self.major_radius);
+        let minor2 = f64::from(self.minor_radius) * f64::from(self.minor_radius);
+
+        let sum_d_sqrd = dx * dx + dy * dy + dz * dz;
+        let e = ox * ox + oy * oy + oz * oz - major2 - minor2;
+        let f = ox * dx + oy * dy + oz * dz;
+        let four_major2 = 4.0 * major2;
+
+        let coefficients = [
+            e * e - four_major2 * (minor2 - oy * oy),
+            4.0 * f * e + 2.0 * four_major2 * oy * dy,
+            2.0 * sum_d_sqrd * e + 4.0 * f * f + four_major2 * dy * dy,
+            4.0 * sum_d_sqrd * f,
+            sum_d_sqrd * sum_d_sqrd,
+        ];
+
+        let t = solve_quartic(coefficients)
+            .into_iter()
+            .map(|root| root as f32)
+            .filter(|&root| root > t_min && root < t_max)
+            .fold(f32::MAX, f32::min);
+        if t == f32::MAX {
+            return None;
+        }
+
+        let local_hit_point = ray.point_at_parameter(t);
+        let p = local_hit_point - self.center;
+        let ring = p.magnitude2() - self.major_radius * self.major_radius
+            - self.minor_radius * self.minor_radius;
+        let normal = vec3(
+            p.x * ring,
+            p.y * (ring + 2.0 * self.major_radius * self.major_radius),
+            p.z * ring,
+        )
+        .normalize();
+
+        let (u, _) = polar_uv(p.x, p.z, self.major_radius);
+        let tube_angle = f32::atan2(p.y, (p.x * p.x + p.z * p.z).sqrt() - self.major_radius);
+        let v = (tube_angle + f32::consts::PI) / (2.0 * f32::consts::PI);
+
+        let tangent = azimuthal_tangent(&p);
+
+        Some(ShadeRecord {
+            intersect_parameter: t,
+            local_hit_point,
+            object_hit_point: Point3::from_vec(p),
+            normal,
+            geometric_normal: normal,
+            tangent,
+            bitangent: normal.cross(tangent),
+            material: &self.material,
+            u,
+            v,
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = vec3(outer, self.minor_radius, outer);
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Texture;
+
+    // Coefficients of the monic polynomial with the given roots, lowest degree first.
+    fn from_roots(roots: [f64; 4]) -> [f64; 5] {
+        roots.iter().fold([1.0, 0.0, 0.0, 0.0, 0.0], |c, &root| {
+            [-root * c[0], c[0] - root * c[1], c[1] - root * c[2], c[2] - root * c[3], c[3]]
+        })
+    }
+
+    fn sorted_roots(c: [f64; 5]) -> Vec<f64> {
+        let mut roots = solve_quartic(c);
+        roots.sort_by(f64::total_cmp);
+        roots
+    }
+
+    fn assert_roots(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "roots {:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "roots {:?}, expected {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn quartic_with_four_distinct_roots() {
+        let coefficients = from_roots([-3.0, -0.5, 1.0, 2.5]).map(|c| 2.0 * c);
+        assert_roots(&sorted_roots(coefficients), &[-3.0, -0.5, 1.0, 2.5]);
+    }
+
+    #[test]
+    fn biquadratic_quartic() {
+        // (x^2 - 1)(x^2 - 4) has no odd terms.
+        assert_roots(
+            &sorted_roots([4.0, 0.0, -5.0, 0.0, 1.0]),
+            &[-2.0, -1.0, 1.0, 2.0],
+        );
+    }
+
+    #[test]
+    fn quartic_with_two_real_roots() {
+        // (x - 1)(x + 2)(x^2 + 1)
+        assert_roots(&sorted_roots([-2.0, 1.0, -1.0, 1.0, 1.0]), &[-2.0, 1.0]);
+    }
+
+    #[test]
+    fn quartic_without_real_roots() {
+        // (x^2 + 1)(x^2 + 4)
+        assert!(solve_quartic([4.0, 0.0, 5.0, 0.0, 1.0]).is_empty());
+    }
+
+    #[test]
+    fn ray_through_torus_hits_the_near_side_of_the_tube() {
+        let torus = Torus::new(
+            Point3::new(0.0, 0.0, 0.0),
+            2.0,
+            0.5,
+            Material::Lambertian {
+                texture: Texture::scalar(0.5),
+            },
+        );
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), 0.0);
+
+        let near = torus
+            .hit(&ray, 0.001, f32::MAX)
+            .expect("the ray crosses the tube");
+        assert!((near.intersect_parameter - 2.5).abs() < 1e-4);
+        assert!((near.normal - vec3(-1.0, 0.0, 0.0)).magnitude() < 1e-4);
+
+        // From inside the hole, the ray next enters the far side of the tube.
+        let far = torus
+            .hit(&ray, 4.0, f32::MAX)
+            .expect("the ray crosses the tube again");
+        assert!((far.intersect_parameter - 6.5).abs() < 1e-4);
+    }
+}
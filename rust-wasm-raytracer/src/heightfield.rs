@@ -0,0 +1,323 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use std::f32;
+
+use crate::aabb::Aabb;
+use crate::geometric_objects::GeometricObject;
+use crate::materials::Material;
+use crate::noise::Perlin;
+use crate::shade_record::ShadeRecord;
+use crate::Ray;
+
+// Regular grid of heights spanning `extent.x` by `extent.z` from `origin`, heights are
+// multiplied by `extent.y`. Cells are split into two triangles and shaded with interpolated
+// vertex normals.
+pub struct Heightfield {
+    origin: Point3<f32>,
+    extent: Vector3<f32>,
+    nx: usize,
+    nz: usize,
+    heights: Vec<f32>,
+    normals: Vec<Vector3<f32>>,
+    bounds: Aabb,
+    material: Material,
+}
+
+// Möller-Trumbore, returns the ray parameter and the barycentric coordinates of `b` and `c`.
+fn triangle_hit(
+    ray: &Ray,
+    a: &Point3<f32>,
+    b: &Point3<f32>,
+    c: &Point3<f32>,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < 1e-10 {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+    let s = ray.origin - a;
+    let beta = s.dot(p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&beta) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let gamma = ray.direction.dot(q) * inverse_determinant;
+    if gamma < 0.0 || beta + gamma > 1.0 {
+        return None;
+    }
+    Some((edge2.dot(q) * inverse_determinant, beta, gamma))
+}
+
+impl Heightfield {
+    pub fn new(
+        origin: Point3<f32>,
+        extent: Vector3<f32>,
+        nx: usize,
+        nz: usize,
+        heights: Vec<f32>,
+        material: Material,
+    ) -> Result<Self, String> {
+        if nx < 2 || nz < 2 {
+            return Err(format!(
+                "heightfield needs at least 2x2 samples, got {}x{}",
+                nx, nz
+            ));
+        }
+        if heights.len() != nx * nz {
+            return Err(format!(
+                "expected {} heights, got {}",
+                nx * nz,
+                heights.len()
+            ));
+        }
+
+        let heights: Vec<f32> = heights.into_iter().map(|h| h * extent.y).collect();
+        let (min_height, max_height) = heights
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &h| (lo.min(h), hi.max(h)));
+        let bounds = Aabb::new(
+            Point3::new(origin.x, origin.y + min_height - 0.0001, origin.z),
+            Point3::new(
+                origin.x + extent.x,
+                origin.y + max_height + 0.0001,
+                origin.z + extent.z,
+            ),
+        );
+
+        let mut heightfield = Heightfield {
+            origin,
+            extent,
+            nx,
+            nz,
+            heights,
+            normals: Vec::new(),
+            bounds,
+            material,
+        };
+        heightfield.normals = (0..nz)
+            .flat_map(|j| (0..nx).map(move |i| (i, j)))
+            .map(|(i, j)| heightfield.vertex_normal(i, j))
+            .collect();
+        Ok(heightfield)
+    }
+
+    // 8-bit grayscale pixels, row by row along z, mapped to heights in [0, 1].
+    pub fn from_grayscale(
+        origin: Point3<f32>,
+        extent: Vector3<f32>,
+        width: usize,
+        height: usize,
+        pixels: &[u8],
+        material: Material,
+    ) -> Result<Self, String> {
+        let heights = pixels.iter().map(|&p| f32::from(p) / 255.0).collect();
+        Heightfield::new(origin, extent, width, height, heights, material)
+    }
+
+    // Procedural terrain from fractal Brownian motion of Perlin noise, spanning `perlin.scale`
+    // noise cells along each side.
+    pub fn from_noise(
+        origin: Point3<f32>,
+        extent: Vector3<f32>,
+        resolution: usize,
+        perlin: &Perlin,
+        octaves: u8,
+        material: Material,
+    ) -> Result<Self, String> {
+        let heights = (0..resolution)
+            .flat_map(|j| (0..resolution).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let mut acc = 0.0;
+                let mut temp_p = Point3::new(
+                    i as f32 / resolution as f32,
+                    0.5 / perlin.scale,
+                    j as f32 / resolution as f32,
+                );
+                let mut weight = 1.0;
+                for _i in 0..octaves {
+                    acc += weight * perlin.generate_noise(&temp_p);
+                    weight *= 0.5;
+                    temp_p *= 2.0;
+                }
+                0.5 * (1.0 + acc)
+            })
+            .collect();
+        Heightfield::new(origin, extent, resolution, resolution, heights, material)
+    }
+
+    #[inline]
+    fn cell_size(&self) -> (f32, f32) {
+        (
+            self.extent.x / (self.nx - 1) as f32,
+            self.extent.z / (self.nz - 1) as f32,
+        )
+    }
+
+    #[inline]
+    fn height(&self, i: usize, j: usize) -> f32 {
+        self.heights[i + j * self.nx]
+    }
+
+    #[inline]
+    fn vertex(&self, i: usize, j: usize) -> Point3<f32> {
+        let (dx, dz) = self.cell_size();
+        Point3::new(
+            self.origin.x + i as f32 * dx,
+            self.origin.y + self.height(i, j),
+            self.origin.z + j as f32 * dz,
+        )
+    }
+
+    fn vertex_normal(&self, i: usize, j: usize) -> Vector3<f32> {
+        let (dx, dz) = self.cell_size();
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.nx - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.nz - 1));
+        let slope_x = (self.height(i1, j) - self.height(i0, j)) / ((i1 - i0) as f32 * dx);
+        let slope_z = (self.height(i, j1) - self.height(i, j0)) / ((j1 - j0) as f32 * dz);
+        vec3(-slope_x, 1.0, -slope_z).normalize()
+    }
+
+    fn cell_hit(
+        &self,
+        ray: &Ray,
+        i: usize,
+        j: usize,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<ShadeRecord<'_>> {
+        let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+
+        [[0, 1, 2], [0, 2, 3]]
+            .iter()
+            .filter_map(|triangle| {
+                let [a, b, c] = triangle.map(|k| corners[k]);
+                triangle_hit(
+                    ray,
+                    &self.vertex(a.0, a.1),
+                    &self.vertex(b.0, b.1),
+                    &self.vertex(c.0, c.1),
+                )
+                .filter(|&(t, _, _)| t > t_min && t < t_max)
+                .map(|(t, beta, gamma)| (t, beta, gamma, a, b, c))
+            })
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+            .map(|(t, beta, gamma, a, b, c)| {
+                let normal_at = |(vi, vj): (usize, usize)| self.normals[vi + vj * self.nx];
+                let normal = (normal_at(a) * (1.0 - beta - gamma)
+                    + normal_at(b) * beta
+                    + normal_at(c) * gamma)
+                    .normalize();
+                let local_hit_point = ray.point_at_parameter(t);
+                let (va, vb, vc) = (
+                    self.vertex(a.0, a.1),
+                    self.vertex(b.0, b.1),
+                    self.vertex(c.0, c.1),
+                );
+                let face_normal = (vb - va).cross(vc - va).normalize();
+                let geometric_normal = if face_normal.dot(normal) < 0.0 {
+                    -face_normal
+                } else {
+                    face_normal
+                };
+
+                ShadeRecord {
+                    intersect_parameter: t,
+                    local_hit_point,
+                    object_hit_point: Point3::from_vec(local_hit_point - self.origin),
+                    normal,
+                    geometric_normal,
+                    tangent: vec3(1.0, 0.0, 0.0),
+                    bitangent: vec3(0.0, 0.0, 1.0),
+                    material: &self.material,
+                    u: (local_hit_point.x - self.origin.x) / self.extent.x,
+                    v: (local_hit_point.z - self.origin.z) / self.extent.z,
+                }
+            })
+    }
+}
+
+impl GeometricObject for Heightfield {
+    // 2D DDA over the cells crossed by the ray's footprint, cells whose height range doesn't
+    // overlap the ray's height range inside the cell are skipped without triangle tests.
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        let (t_enter, t_exit) = self.bounds.hit(ray, t_min, t_max)?;
+        let (dx, dz) = self.cell_size();
+        let (last_i, last_j) = (self.nx as i64 - 2, self.nz as i64 - 2);
+
+        let start = ray.point_at_parameter(t_enter);
+        let mut i = (((start.x - self.origin.x) / dx).floor() as i64).clamp(0, last_i);
+        let mut j = (((start.z - self.origin.z) / dz).floor() as i64).clamp(0, last_j);
+
+        let (step_i, t_delta_x, mut t_next_x) = match ray.direction.x {
+            d if d > 0.0 => (
+                1,
+                dx / d,
+                (self.origin.x + (i + 1) as f32 * dx - ray.origin.x) / d,
+            ),
+            d if d < 0.0 => (
+                -1,
+                -dx / d,
+                (self.origin.x + i as f32 * dx - ray.origin.x) / d,
+            ),
+            _ => (0, f32::MAX, f32::MAX),
+        };
+        let (step_j, t_delta_z, mut t_next_z) = match ray.direction.z {
+            d if d > 0.0 => (
+                1,
+                dz / d,
+                (self.origin.z + (j + 1) as f32 * dz - ray.origin.z) / d,
+            ),
+            d if d < 0.0 => (
+                -1,
+                -dz / d,
+                (self.origin.z + j as f32 * dz - ray.origin.z) / d,
+            ),
+            _ => (0, f32::MAX, f32::MAX),
+        };
+
+        let mut t_cell_start = t_enter;
+        loop {
+            let t_cell_end = t_next_x.min(t_next_z).min(t_exit);
+            let (ci, cj) = (i as usize, j as usize);
+
+            let cell_heights = [
+                self.height(ci, cj),
+                self.height(ci + 1, cj),
+                self.height(ci, cj + 1),
+                self.height(ci + 1, cj + 1),
+            ];
+            let cell_min = cell_heights.iter().cloned().fold(f32::MAX, f32::min) + self.origin.y;
+            let cell_max = cell_heights.iter().cloned().fold(f32::MIN, f32::max) + self.origin.y;
+            let y_start = ray.origin.y + t_cell_start * ray.direction.y;
+            let y_end = ray.origin.y + t_cell_end * ray.direction.y;
+
+            if y_start.min(y_end) <= cell_max + 0.0001 && y_start.max(y_end) >= cell_min - 0.0001 {
+                if let Some(rec) = self.cell_hit(ray, ci, cj, t_min, t_max) {
+                    return Some(rec);
+                }
+            }
+
+            if t_cell_end >= t_exit {
+                return None;
+            }
+            if t_next_x < t_next_z {
+                i += step_i;
+                t_next_x += t_delta_x;
+            } else {
+                j += step_j;
+                t_next_z += t_delta_z;
+            }
+            if i < 0 || i > last_i || j < 0 || j > last_j {
+                return None;
+            }
+            t_cell_start = t_cell_end;
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
@@ -0,0 +1,596 @@
+use cgmath::{vec4, Vector4};
+use std::f32;
+
+#[derive(Clone, Copy)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl WrapMode {
+    #[inline]
+    fn apply(&self, coordinate: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => coordinate.rem_euclid(size),
+            WrapMode::Clamp => coordinate.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let period = coordinate.rem_euclid(2 * size);
+                if period < size {
+                    period
+                } else {
+                    2 * size - 1 - period
+                }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+// RGBA image with linear color values, rows are stored from the top of the image down.
+#[derive(Clone)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector4<f32>>,
+}
+
+#[inline]
+fn srgb_to_linear(value: u8) -> f32 {
+    (f32::from(value) / 255.0).powf(2.2)
+}
+
+// Number of values in an image with `channels` per pixel. Rejects empty images before their rows
+// are split up for decoding, and sizes from a header that overflow.
+fn sample_count(width: usize, height: usize, channels: usize) -> Result<usize, String> {
+    width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .filter(|&count| count > 0)
+        .ok_or_else(|| format!("invalid image size {}x{}", width, height))
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vector4<f32>>) -> Result<Self, String> {
+        let count = sample_count(width, height, 1)?;
+        if pixels.len() != count {
+            return Err(format!(
+                "expected {} pixels for a {}x{} image, got {}",
+                count,
+                width,
+                height,
+                pixels.len()
+            ));
+        }
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // Builds an image from 8-bit channels, color is decoded from sRGB unless the image holds
+    // data such as normals or heights. Alpha is always linear.
+    fn from_8bit(
+        width: usize,
+        height: usize,
+        channels: usize,
+        data: &[u8],
+        srgb: bool,
+    ) -> Result<Self, String> {
+        let count = sample_count(width, height, channels)?;
+        if data.len() < count {
+            return Err(String::from("image data is truncated"));
+        }
+        let color = |value: u8| {
+            if srgb {
+                srgb_to_linear(value)
+            } else {
+                f32::from(value) / 255.0
+            }
+        };
+        let pixels = data
+            .chunks_exact(channels)
+            .take(count / channels)
+            .map(|c| match channels {
+                1 => {
+                    let l = color(c[0]);
+                    vec4(l, l, l, 1.0)
+                }
+                2 => {
+                    let l = color(c[0]);
+                    vec4(l, l, l, f32::from(c[1]) / 255.0)
+                }
+                3 => vec4(color(c[0]), color(c[1]), color(c[2]), 1.0),
+                _ => vec4(
+                    color(c[0]),
+                    color(c[1]),
+                    color(c[2]),
+                    f32::from(c[3]) / 255.0,
+                ),
+            })
+            .collect();
+        Image::new(width, height, pixels)
+    }
+
+    // Detects the format from the file contents, supports PNG, binary/ASCII PPM and TGA, and
+    // Radiance HDR and PFM for high dynamic range images.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        Image::decode_with(bytes, true)
+    }
+
+    // Like `decode`, but 8-bit values are kept as they are stored, for normal maps and
+    // height maps.
+    pub fn decode_data(bytes: &[u8]) -> Result<Self, String> {
+        Image::decode_with(bytes, false)
+    }
+
+    fn decode_with(bytes: &[u8], srgb: bool) -> Result<Self, String> {
+        match bytes {
+            [0x89, b'P', b'N', b'G', ..] => Image::decode_png(bytes, srgb),
+            [b'P', b'3' | b'6', ..] => Image::decode_ppm(bytes, srgb),
+            [b'P', b'F' | b'f', ..] => Image::decode_pfm(bytes),
+            [b'#', b'?', ..] => Image::decode_hdr(bytes),
+            _ => Image::decode_tga(bytes, srgb),
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    pub fn pixel(&self, x: usize, y: usize) -> Vector4<f32> {
+        self.pixels[x + y * self.width]
+    }
+
+    fn decode_png(bytes: &[u8], srgb: bool) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(|e| e.to_string())?;
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            png::ColorType::Indexed => return Err(String::from("unexpanded indexed PNG")),
+        };
+        let (width, height) = (info.width as usize, info.height as usize);
+        sample_count(width, height, channels)?;
+
+        // Rows may be padded, repack them tightly before conversion.
+        let packed: Vec<u8> = data
+            .chunks(info.line_size)
+            .take(height)
+            .flat_map(|row| row[..width * channels].iter().cloned())
+            .collect();
+        Image::from_8bit(width, height, channels, &packed, srgb)
+    }
+
+    fn decode_ppm(bytes: &[u8], srgb: bool) -> Result<Self, String> {
+        // The header is whitespace separated, comments run from '#' to the end of the line.
+        let mut position = 2;
+        let mut header = [0_usize; 3];
+        for value in header.iter_mut() {
+            loop {
+                match bytes.get(position) {
+                    Some(b'#') => {
+                        while !matches!(bytes.get(position), Some(b'\n') | None) {
+                            position += 1;
+                        }
+                    }
+                    Some(c) if c.is_ascii_whitespace() => position += 1,
+                    _ => break,
+                }
+            }
+            let start = position;
+            while matches!(bytes.get(position), Some(c) if c.is_ascii_digit()) {
+                position += 1;
+            }
+            *value = std::str::from_utf8(&bytes[start..position])
+                .map_err(|e| e.to_string())?
+                .parse()
+                .map_err(|_| String::from("malformed PPM header"))?;
+        }
+        let [width, height, max_value] = header;
+        if max_value == 0 || max_value > 65535 {
+            return Err(format!("unsupported PPM maximum value {}", max_value));
+        }
+        let count = sample_count(width, height, 3)?;
+
+        let samples: Vec<usize> = if bytes[1] == b'6' {
+            // Exactly one whitespace byte separates the header from the binary data.
+            let data = bytes.get(position + 1..).unwrap_or(&[]);
+            if max_value < 256 {
+                data.iter().take(count).map(|&b| usize::from(b)).collect()
+            } else {
+                data.chunks_exact(2)
+                    .take(count)
+                    .map(|c| usize::from(u16::from_be_bytes([c[0], c[1]])))
+                    .collect()
+            }
+        } else {
+            std::str::from_utf8(&bytes[position..])
+                .map_err(|e| e.to_string())?
+                .split_whitespace()
+                .take(count)
+                .map(|token| {
+                    token
+                        .parse()
+                        .map_err(|_| String::from("malformed PPM data"))
+                })
+                .collect::<Result<_, _>>()?
+        };
+        if samples.len() < count {
+            return Err(String::from("image data is truncated"));
+        }
+
+        let data: Vec<u8> = samples
+            .into_iter()
+            .map(|s| ((s.min(max_value) * 255 + max_value / 2) / max_value) as u8)
+            .collect();
+        Image::from_8bit(width, height, 3, &data, srgb)
+    }
+
+    fn decode_tga(bytes: &[u8], srgb: bool) -> Result<Self, String> {
+        if bytes.len() < 18 {
+            return Err(String::from("unrecognized image format"));
+        }
+        let id_length = usize::from(bytes[0]);
+        let color_map_type = bytes[1];
+        let image_type = bytes[2];
+        let width = usize::from(u16::from_le_bytes([bytes[12], bytes[13]]));
+        let height = usize::from(u16::from_le_bytes([bytes[14], bytes[15]]));
+        let bits_per_pixel = bytes[16];
+        let top_to_bottom = bytes[17] & 0x20 != 0;
+
+        let channels = match (color_map_type, image_type, bits_per_pixel) {
+            (0, 2 | 10, 24) => 3,
+            (0, 2 | 10, 32) => 4,
+            (0, 3 | 11, 8) => 1,
+            _ => return Err(String::from("unsupported or unrecognized image format")),
+        };
+        let count = sample_count(width, height, channels)?;
+        let mut data = bytes.get(18 + id_length..).unwrap_or(&[]).iter();
+        // The header alone cannot be trusted to size the buffer.
+        let mut pixels: Vec<u8> = Vec::with_capacity(count.min(bytes.len()));
+
+        // TGA stores colors as BGR(A), swap into RGB(A) while reading.
+        let read_pixel = |data: &mut std::slice::Iter<u8>| -> Result<Vec<u8>, String> {
+            let mut pixel: Vec<u8> = data.by_ref().take(channels).cloned().collect();
+            if pixel.len() < channels {
+                return Err(String::from("image data is truncated"));
+            }
+            if channels >= 3 {
+                pixel.swap(0, 2);
+            }
+            Ok(pixel)
+        };
+
+        while pixels.len() < count {
+            if image_type >= 9 {
+                let packet = *data
+                    .next()
+                    .ok_or_else(|| String::from("image data is truncated"))?;
+                let run = usize::from(packet & 0x7f) + 1;
+                if packet & 0x80 != 0 {
+                    let pixel = read_pixel(&mut data)?;
+                    (0..run).for_each(|_| pixels.extend_from_slice(&pixel));
+                } else {
+                    for _ in 0..run {
+                        pixels.extend(read_pixel(&mut data)?);
+                    }
+                }
+            } else {
+                pixels.extend(read_pixel(&mut data)?);
+            }
+        }
+        pixels.truncate(count);
+
+        if !top_to_bottom {
+            let row_length = width * channels;
+            pixels = pixels
+                .chunks(row_length)
+                .rev()
+                .flat_map(|row| row.iter().cloned())
+                .collect();
+        }
+        Image::from_8bit(width, height, channels, &pixels, srgb)
+    }
+
+    fn decode_hdr(bytes: &[u8]) -> Result<Self, String> {
+        let mut lines = bytes.split(|&b| b == b'\n');
+        let mut header_length = 0;
+        let mut resolution = None;
+
+        // Header lines run until an empty line, the resolution string follows it.
+        for line in lines.by_ref() {
+            header_length += line.len() + 1;
+            if line.starts_with(b"FORMAT=") && line != b"FORMAT=32-bit_rle_rgbe" {
+                return Err(String::from("only RGBE Radiance images are supported"));
+            }
+            if line.is_empty() {
+                let line = lines
+                    .next()
+                    .ok_or_else(|| String::from("missing HDR resolution"))?;
+                header_length += line.len() + 1;
+                resolution = Some(String::from_utf8_lossy(line).into_owned());
+                break;
+            }
+        }
+        let resolution = resolution.ok_or_else(|| String::from("missing HDR resolution"))?;
+        let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", h, "+X", w] => (
+                h.parse::<usize>().map_err(|e| e.to_string())?,
+                w.parse::<usize>().map_err(|e| e.to_string())?,
+            ),
+            _ => return Err(format!("unsupported HDR orientation {}", resolution)),
+        };
+        let count = sample_count(width, height, 1)?;
+
+        let mut data = bytes.get(header_length..).unwrap_or(&[]).iter().cloned();
+        let mut next_byte = || {
+            data.next()
+                .ok_or_else(|| String::from("image data is truncated"))
+        };
+        let malformed_scanline = || String::from("malformed HDR scanline");
+        let mut rgbe = vec![[0_u8; 4]; width];
+        // The header alone cannot be trusted to size the buffer.
+        let mut pixels = Vec::with_capacity(count.min(bytes.len()));
+
+        for _ in 0..height {
+            let marker = [next_byte()?, next_byte()?, next_byte()?, next_byte()?];
+            let run_length_encoded = (8..32768).contains(&width)
+                && marker[0] == 2
+                && marker[1] == 2
+                && usize::from(marker[2]) << 8 | usize::from(marker[3]) == width;
+
+            if run_length_encoded {
+                // Each channel of the scanline is encoded separately.
+                for channel in 0..4 {
+                    let mut x = 0;
+                    while x < width {
+                        let count = next_byte()?;
+                        if count > 128 {
+                            let value = next_byte()?;
+                            for _ in 0..count - 128 {
+                                rgbe.get_mut(x).ok_or_else(malformed_scanline)?[channel] = value;
+                                x += 1;
+                            }
+                        } else {
+                            for _ in 0..count {
+                                rgbe.get_mut(x).ok_or_else(malformed_scanline)?[channel] =
+                                    next_byte()?;
+                                x += 1;
+                            }
+                        }
+                    }
+                }
+            } else {
+                rgbe[0] = marker;
+                for pixel in rgbe.iter_mut().skip(1) {
+                    *pixel = [next_byte()?, next_byte()?, next_byte()?, next_byte()?];
+                }
+            }
+
+            pixels.extend(rgbe.iter().map(|&[r, g, b, e]| {
+                if e == 0 {
+                    vec4(0.0, 0.0, 0.0, 1.0)
+                } else {
+                    let scale = 2.0_f32.powi(i32::from(e) - 136);
+                    vec4(
+                        f32::from(r) * scale,
+                        f32::from(g) * scale,
+                        f32::from(b) * scale,
+                        1.0,
+                    )
+                }
+            }));
+        }
+        Image::new(width, height, pixels)
+    }
+
+    fn decode_pfm(bytes: &[u8]) -> Result<Self, String> {
+        let channels = if bytes[1] == b'F' { 3 } else { 1 };
+        let mut position = 2;
+        let mut header = Vec::with_capacity(3);
+
+        while header.len() < 3 {
+            while matches!(bytes.get(position), Some(c) if c.is_ascii_whitespace()) {
+                position += 1;
+            }
+            let start = position;
+            while matches!(bytes.get(position), Some(c) if !c.is_ascii_whitespace()) {
+                position += 1;
+            }
+            header.push(std::str::from_utf8(&bytes[start..position]).map_err(|e| e.to_string())?);
+        }
+        let width = header[0].parse::<usize>().map_err(|e| e.to_string())?;
+        let height = header[1].parse::<usize>().map_err(|e| e.to_string())?;
+        let little_endian = header[2].parse::<f32>().map_err(|e| e.to_string())? < 0.0;
+        let count = sample_count(width, height, channels)?;
+
+        let values: Vec<f32> = bytes
+            .get(position + 1..)
+            .unwrap_or(&[])
+            .chunks_exact(4)
+            .take(count)
+            .map(|c| {
+                let raw = [c[0], c[1], c[2], c[3]];
+                if little_endian {
+                    f32::from_le_bytes(raw)
+                } else {
+                    f32::from_be_bytes(raw)
+                }
+            })
+            .collect();
+        if values.len() < count {
+            return Err(String::from("image data is truncated"));
+        }
+
+        // Scanlines are stored from the bottom of the image up.
+        let pixels = values
+            .chunks(width * channels)
+            .rev()
+            .flat_map(|row| row.chunks(channels))
+            .map(|c| match c {
+                [l] => vec4(*l, *l, *l, 1.0),
+                _ => vec4(c[0], c[1], c[2], 1.0),
+            })
+            .collect();
+        Image::new(width, height, pixels)
+    }
+
+    #[inline]
+    fn texel(&self, x: i64, y: i64, wrap: WrapMode) -> Vector4<f32> {
+        self.pixels[wrap.apply(x, self.width) + wrap.apply(y, self.height) * self.width]
+    }
+
+    // Bilinear lookup, v = 0 is the bottom row of the image.
+    pub fn sample(&self, u: f32, v: f32, wrap: WrapMode) -> Vector4<f32> {
+        let x = u * self.width as f32 - 0.5;
+        let y = (1.0 - v) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0, wrap) * (1.0 - tx) + self.texel(x0 + 1, y0, wrap) * tx;
+        let bottom =
+            self.texel(x0, y0 + 1, wrap) * (1.0 - tx) + self.texel(x0 + 1, y0 + 1, wrap) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_error(result: Result<Image, String>, message: &str) {
+        match result {
+            Ok(image) => panic!("decoded a {}x{} image", image.width(), image.height()),
+            Err(error) => assert!(error.contains(message), "unexpected error {:?}", error),
+        }
+    }
+
+    // Uncompressed TGA header for a bottom-to-top image of 24-bit pixels.
+    fn tga_header(width: u16, height: u16) -> Vec<u8> {
+        let [w0, w1] = width.to_le_bytes();
+        let [h0, h1] = height.to_le_bytes();
+        vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, w0, w1, h0, h1, 24, 0]
+    }
+
+    #[test]
+    fn decodes_binary_and_ascii_ppm() {
+        let bytes = b"P6\n# comment\n2 1\n255\n\xff\x00\x00\x00\x00\xff";
+        let binary = Image::decode_data(bytes).unwrap();
+        assert_eq!((binary.width(), binary.height()), (2, 1));
+        assert_eq!(binary.pixel(0, 0), vec4(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(binary.pixel(1, 0), vec4(0.0, 0.0, 1.0, 1.0));
+
+        let ascii = Image::decode_data(b"P3 1 1 15 15 0 15").unwrap();
+        assert_eq!(ascii.pixel(0, 0), vec4(1.0, 0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_truncated_ppm() {
+        assert_error(Image::decode(b"P6 2 2 255\n\x00\x00\x00"), "truncated");
+        assert_error(Image::decode(b"P3 1 1 255 0 0"), "truncated");
+        assert_error(Image::decode(b"P6 2"), "malformed PPM header");
+    }
+
+    #[test]
+    fn rejects_zero_and_overflowing_ppm_sizes() {
+        assert_error(Image::decode(b"P6 0 4 255\n"), "invalid image size");
+        assert_error(
+            Image::decode(b"P6 18446744073709551615 2 255\n\x00"),
+            "invalid image size",
+        );
+    }
+
+    #[test]
+    fn decodes_tga_bottom_to_top() {
+        let mut bytes = tga_header(1, 2);
+        bytes.extend([255, 0, 0, 0, 0, 255]);
+        let image = Image::decode_data(&bytes).unwrap();
+        // BGR on disk, the last row stored is the top of the image.
+        assert_eq!(image.pixel(0, 0), vec4(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(image.pixel(0, 1), vec4(0.0, 0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn decodes_run_length_encoded_tga() {
+        let mut bytes = tga_header(3, 1);
+        bytes[2] = 10;
+        bytes[17] = 0x20;
+        // A run of two green pixels followed by one literal red pixel.
+        bytes.extend([0x81, 0, 255, 0, 0x00, 0, 0, 255]);
+        let image = Image::decode_data(&bytes).unwrap();
+        assert_eq!(image.pixel(1, 0), vec4(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(image.pixel(2, 0), vec4(1.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_truncated_and_empty_tga() {
+        assert_error(Image::decode(&[0; 10]), "unrecognized image format");
+        let mut bytes = tga_header(2, 2);
+        bytes.extend([0; 5]);
+        assert_error(Image::decode(&bytes), "truncated");
+        assert_error(Image::decode(&tga_header(0, 2)), "invalid image size");
+    }
+
+    #[test]
+    fn decodes_hdr_and_rejects_truncated_scanlines() {
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n";
+        let mut bytes = header.to_vec();
+        bytes.extend([128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = Image::decode(&bytes).unwrap();
+        assert_eq!(image.pixel(0, 0), vec4(1.0, 0.5, 0.0, 1.0));
+        assert_eq!(image.pixel(1, 0), vec4(0.0, 0.0, 0.0, 1.0));
+
+        assert_error(Image::decode(&bytes[..bytes.len() - 1]), "truncated");
+        assert_error(
+            Image::decode(b"#?RADIANCE\n\n-Y 0 +X 2\n"),
+            "invalid image size",
+        );
+        assert_error(Image::decode(b"#?RADIANCE\n"), "missing HDR resolution");
+    }
+
+    #[test]
+    fn decodes_pfm_and_rejects_truncated_data() {
+        let mut bytes = b"Pf\n1 2\n-1.0\n".to_vec();
+        bytes.extend(0.25_f32.to_le_bytes());
+        bytes.extend(2.0_f32.to_le_bytes());
+        let image = Image::decode(&bytes).unwrap();
+        // Scanlines are stored from the bottom up.
+        assert_eq!(image.pixel(0, 0), vec4(2.0, 2.0, 2.0, 1.0));
+        assert_eq!(image.pixel(0, 1), vec4(0.25, 0.25, 0.25, 1.0));
+
+        assert_error(Image::decode(&bytes[..bytes.len() - 1]), "truncated");
+        assert_error(Image::decode(b"PF\n0 0\n-1.0\n"), "invalid image size");
+    }
+
+    #[test]
+    fn rejects_truncated_png() {
+        assert!(Image::decode(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a]).is_err());
+    }
+
+    #[test]
+    fn new_checks_the_pixel_count() {
+        assert!(Image::new(2, 2, vec![vec4(0.0, 0.0, 0.0, 1.0); 4]).is_ok());
+        assert_error(
+            Image::new(2, 2, vec![vec4(0.0, 0.0, 0.0, 1.0); 3]),
+            "expected 4 pixels",
+        );
+        assert_error(Image::new(0, 0, Vec::new()), "invalid image size");
+    }
+}
@@ -0,0 +1,217 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use std::f32;
+
+use crate::layered::{BaseLayer, LayeredBsdf};
+use crate::materials::{beer_lambert, reflected_vector, Material::*};
+use crate::microfacet::{fresnel_conductor, sample_cosine_hemisphere, Frame, OrenNayarBrdf};
+use crate::spectrum::REFERENCE_WAVELENGTH;
+use crate::thin_film::RGB_WAVELENGTHS;
+use crate::world::World;
+use crate::{
+    background_color, dielectric_scatter, film_reflectance, punctual_lighting,
+    sample_background_light, Ray,
+};
+
+// Recursion is limited much earlier than in the path tracer, dielectrics trace both the
+// reflected and the refracted ray.
+const WHITTED_MAX_DEPTH: usize = 8;
+
+// Classic Whitted ray tracing: direct lighting at diffuse surfaces, perfect mirror
+// reflection for metals and Fresnel weighted reflection and refraction for dielectrics.
+pub fn whitted_color(ray: &Ray, world: &World, depth: usize) -> Vector3<f32> {
+    let rec = match world.trace(ray) {
+        Some(rec) => rec,
+        None => return background_color(ray, world, false),
+    };
+    if depth >= WHITTED_MAX_DEPTH {
+        return vec3(0.0, 0.0, 0.0);
+    }
+
+    let lambert = |_: &Vector3<f32>| vec3(1.0, 1.0, 1.0) / f32::consts::PI;
+    let trace = |direction: Vector3<f32>| {
+        whitted_color(
+            &Ray::new(rec.local_hit_point, direction, ray.time),
+            world,
+            depth + 1,
+        )
+    };
+    let dielectric = |refractive_index: f32| {
+        let (reflected, refracted, reflect_prob) =
+            dielectric_scatter(ray, &rec.normal, refractive_index);
+        if reflect_prob >= 1.0 {
+            trace(reflected)
+        } else {
+            trace(reflected) * reflect_prob + trace(refracted) * (1.0 - reflect_prob)
+        }
+    };
+
+    match &rec.material {
+        Lambertian { texture } => {
+            let Point3 { x: r, y: g, z: b } = texture.value(rec.u, rec.v, &rec.object_hit_point);
+            let v = sample_background_light(&rec, world, ray.time, lambert)
+                + punctual_lighting(&rec, world, ray.time, lambert);
+            vec3(v.x * r, v.y * g, v.z * b)
+        }
+        OrenNayar { texture, sigma } => {
+            let model = OrenNayarBrdf::new(*sigma);
+            let frame = Frame::from_normal(&rec.normal);
+            let wo = frame.to_local(&-ray.direction.normalize());
+            let brdf = |to_light: &Vector3<f32>| {
+                let f = model.evaluate(&wo, &frame.to_local(to_light)) / f32::consts::PI;
+                vec3(f, f, f)
+            };
+            let Point3 { x: r, y: g, z: b } = texture.value(rec.u, rec.v, &rec.object_hit_point);
+            let v = sample_background_light(&rec, world, ray.time, brdf)
+                + punctual_lighting(&rec, world, ray.time, brdf);
+            vec3(v.x * r, v.y * g, v.z * b)
+        }
+        // Light scattered below the surface is approximated as diffuse reflection.
+        Subsurface { albedo, .. } => {
+            let v = sample_background_light(&rec, world, ray.time, lambert)
+                + punctual_lighting(&rec, world, ray.time, lambert);
+            v.mul_element_wise(*albedo)
+        }
+        Metallic { r, g, b } => {
+            let reflected = reflected_vector(&ray.direction.normalize(), &rec.normal);
+            let u = trace(reflected);
+            vec3(u.x * r, u.y * g, u.z * b)
+        }
+        Dielectric {
+            refractive_index,
+            thin_film: None,
+        } => dielectric(*refractive_index),
+        Dielectric {
+            refractive_index,
+            thin_film: Some(film),
+        } => {
+            let (reflected, refracted, reflect_prob) =
+                dielectric_scatter(ray, &rec.normal, *refractive_index);
+            if reflect_prob >= 1.0 {
+                trace(reflected)
+            } else {
+                let reflectance = film_reflectance(ray, &rec.normal, film, *refractive_index, None);
+                trace(reflected).mul_element_wise(reflectance)
+                    + trace(refracted).mul_element_wise(vec3(1.0, 1.0, 1.0) - reflectance)
+            }
+        }
+        DispersiveDielectric { refractive_index } => {
+            dielectric(refractive_index.at(REFERENCE_WAVELENGTH))
+        }
+        DiffuseLight { .. } => rec.material.emitted(&rec, &ray.direction),
+        Masked { .. } | Mix { .. } => unreachable!("masks and mixes are resolved by World::trace"),
+        // Roughness is ignored, absorption is applied to the segment travelled inside the glass.
+        Glass {
+            refractive_index,
+            absorption,
+            ..
+        } => {
+            let v = dielectric(*refractive_index);
+            if ray.direction.dot(rec.normal) > 0.0 {
+                let distance = rec.intersect_parameter * ray.direction.magnitude();
+                v.mul_element_wise(beer_lambert(absorption, distance))
+            } else {
+                v
+            }
+        }
+        // Direct lighting from all reflection lobes, mirror reflection and straight
+        // refraction stand in for the glossy and transmitted light.
+        Principled { bsdf } => {
+            let lobes = bsdf.evaluate(rec.u, rec.v, &rec.object_hit_point);
+            let direction = ray.direction.normalize();
+            let cosine = direction.dot(rec.normal);
+            let normal = if cosine > 0.0 {
+                -rec.normal
+            } else {
+                rec.normal
+            };
+            let frame = Frame::new(&normal, &rec.tangent);
+            let wo = frame.to_local(&-direction);
+
+            let direct = punctual_lighting(&rec, world, ray.time, |to_light| {
+                lobes.reflection(&wo, &frame.to_local(to_light))
+            });
+            let reflected = trace(reflected_vector(&direction, &rec.normal))
+                .mul_element_wise(lobes.specular_reflectance(cosine.abs()));
+            let transmittance = lobes.transmittance();
+            let transmitted = if transmittance != vec3(0.0, 0.0, 0.0) {
+                trace(direction).mul_element_wise(transmittance)
+            } else {
+                vec3(0.0, 0.0, 0.0)
+            };
+            direct + reflected + transmitted
+        }
+        // Direct lighting through the layers, the coat adds a mirror reflection on top.
+        Coated { coat, base } => {
+            let direction = ray.direction.normalize();
+            let cosine = direction.dot(rec.normal);
+            let normal = if cosine > 0.0 {
+                -rec.normal
+            } else {
+                rec.normal
+            };
+            let frame = Frame::new(&normal, &rec.tangent);
+            let base = BaseLayer::from_material(base, coat, rec.u, rec.v, &rec.object_hit_point);
+            let layers = LayeredBsdf::new(coat, base);
+            let wo = frame.to_local(&-direction);
+
+            let direct = punctual_lighting(&rec, world, ray.time, |to_light| {
+                layers.evaluate(&wo, &frame.to_local(to_light))
+            });
+            let reflected = trace(reflected_vector(&direction, &rec.normal));
+            direct + reflected * layers.coat_reflectance(cosine.abs())
+        }
+        // Roughness is ignored, the mirror direction is tinted by the conductor Fresnel term.
+        Conductor {
+            eta, k, thin_film, ..
+        } => {
+            let direction = ray.direction.normalize();
+            let reflected = reflected_vector(&direction, &rec.normal);
+            let cosine = direction.dot(rec.normal).abs();
+            let reflectance = match thin_film {
+                Some(film) => film.reflectance3(cosine, 1.0, eta, k, &RGB_WAVELENGTHS),
+                None => fresnel_conductor(cosine, eta, k),
+            };
+            trace(reflected).mul_element_wise(reflectance)
+        }
+    }
+}
+
+// Fraction of the cosine weighted hemisphere above the first hit that is unoccluded within
+// `radius`, estimated with a single cosine distributed direction.
+pub fn ambient_occlusion(ray: &Ray, world: &World, radius: f32) -> Vector3<f32> {
+    match world.trace(ray) {
+        Some(rec) => {
+            let facing_normal = if ray.direction.dot(rec.normal) > 0.0 {
+                -rec.normal
+            } else {
+                rec.normal
+            };
+            let direction =
+                Frame::from_normal(&facing_normal).to_world(&sample_cosine_hemisphere());
+
+            let occlusion_ray = Ray::new(rec.local_hit_point, direction, ray.time);
+            let visibility = if world.occluded(&occlusion_ray, radius) {
+                0.0
+            } else {
+                1.0
+            };
+            vec3(visibility, visibility, visibility)
+        }
+        None => vec3(1.0, 1.0, 1.0),
+    }
+}
+
+pub fn normals_color(ray: &Ray, world: &World) -> Vector3<f32> {
+    match world.trace(ray) {
+        Some(rec) => (rec.normal.normalize() + vec3(1.0, 1.0, 1.0)) * 0.5,
+        None => vec3(0.0, 0.0, 0.0),
+    }
+}
+
+pub fn albedo_color(ray: &Ray, world: &World) -> Vector3<f32> {
+    match world.trace(ray) {
+        Some(rec) => rec.material.albedo(rec.u, rec.v, &rec.object_hit_point),
+        None => background_color(ray, world, false),
+    }
+}
@@ -0,0 +1,332 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use rand::random;
+use std::f32;
+
+use crate::materials::{beer_lambert, Material};
+use crate::microfacet::{
+    conductor_brdf, fresnel_conductor, fresnel_dielectric, refract_local, sample_conductor,
+    sample_cosine_hemisphere, sample_dielectric, Ggx, OrenNayarBrdf,
+};
+use crate::principled::PrincipledLobes;
+use crate::thin_film::{ThinFilm, RGB_WAVELENGTHS};
+
+// Light bouncing between the coat and the base more often than this is dropped.
+const MAX_LAYER_BOUNCES: usize = 32;
+
+// Fuzz of the `Metallic` material expressed as GGX roughness, when it is used as a base.
+const METALLIC_BASE_ROUGHNESS: f32 = 0.5;
+
+// Clear dielectric layer on top of another material. Light crossing the coat of `thickness`
+// is absorbed according to the Beer-Lambert law with the `absorption` coefficients.
+#[derive(Clone, Copy)]
+pub struct Coat {
+    pub refractive_index: f32,
+    pub roughness: f32,
+    pub thickness: f32,
+    pub absorption: Vector3<f32>,
+}
+
+impl Coat {
+    pub const fn new(
+        refractive_index: f32,
+        roughness: f32,
+        thickness: f32,
+        absorption: Vector3<f32>,
+    ) -> Self {
+        Coat {
+            refractive_index,
+            roughness,
+            thickness,
+            absorption,
+        }
+    }
+
+    // Clear lacquer of a typical car paint or varnish.
+    pub fn clear(roughness: f32) -> Self {
+        Coat::new(1.5, roughness, 0.0, vec3(0.0, 0.0, 0.0))
+    }
+
+    fn attenuation(&self, cosine: f32) -> Vector3<f32> {
+        beer_lambert(&self.absorption, self.thickness / cosine.abs().max(1e-4))
+    }
+}
+
+// Reflection model of the material below a coat, resolved at a surface point. Directions are
+// local to the shading frame and lie inside the coat. Materials without a reflection model of
+// their own are treated as diffuse with their albedo.
+pub enum BaseLayer {
+    Diffuse {
+        albedo: Vector3<f32>,
+    },
+    OrenNayar {
+        albedo: Vector3<f32>,
+        model: OrenNayarBrdf,
+    },
+    Metallic {
+        color: Vector3<f32>,
+        ggx: Ggx,
+    },
+    // Optical constants are relative to the coat the conductor is embedded in.
+    Conductor {
+        eta: Vector3<f32>,
+        k: Vector3<f32>,
+        ggx: Ggx,
+        thin_film: Option<ThinFilm>,
+        outside: f32,
+    },
+    Principled {
+        lobes: Box<PrincipledLobes>,
+    },
+}
+
+impl BaseLayer {
+    pub fn from_material(
+        material: &Material,
+        coat: &Coat,
+        u: f32,
+        v: f32,
+        point: &Point3<f32>,
+    ) -> Self {
+        match material {
+            Material::Metallic { r, g, b } => BaseLayer::Metallic {
+                color: vec3(*r, *g, *b),
+                ggx: Ggx::from_roughness(METALLIC_BASE_ROUGHNESS, 0.0),
+            },
+            Material::Conductor {
+                eta,
+                k,
+                roughness,
+                anisotropy,
+                thin_film,
+            } => BaseLayer::Conductor {
+                eta: *eta,
+                k: *k,
+                ggx: Ggx::from_roughness(*roughness, *anisotropy),
+                thin_film: *thin_film,
+                outside: coat.refractive_index,
+            },
+            Material::OrenNayar { texture, sigma } => {
+                let Point3 { x, y, z } = texture.value(u, v, point);
+                BaseLayer::OrenNayar {
+                    albedo: vec3(x, y, z),
+                    model: OrenNayarBrdf::new(*sigma),
+                }
+            }
+            Material::Principled { bsdf } => BaseLayer::Principled {
+                lobes: Box::new(bsdf.evaluate(u, v, point)),
+            },
+            Material::Masked { material, .. } => {
+                BaseLayer::from_material(material, coat, u, v, point)
+            }
+            Material::Mix { .. } => {
+                BaseLayer::from_material(material.pick_mixed(u, v, point), coat, u, v, point)
+            }
+            _ => BaseLayer::Diffuse {
+                albedo: material.albedo(u, v, point),
+            },
+        }
+    }
+
+    fn conductor_fresnel<'a>(
+        eta: &'a Vector3<f32>,
+        k: &'a Vector3<f32>,
+        thin_film: &'a Option<ThinFilm>,
+        outside: f32,
+    ) -> impl Fn(f32) -> Vector3<f32> + 'a {
+        move |cosine| match thin_film {
+            Some(film) => film.reflectance3(cosine, outside, eta, k, &RGB_WAVELENGTHS),
+            None => fresnel_conductor(cosine, &(eta / outside), &(k / outside)),
+        }
+    }
+
+    // BSDF without the cosine term.
+    fn evaluate(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> Vector3<f32> {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+        match self {
+            BaseLayer::Diffuse { albedo } => albedo / f32::consts::PI,
+            BaseLayer::OrenNayar { albedo, model } => {
+                albedo * (model.evaluate(wo, wi) / f32::consts::PI)
+            }
+            BaseLayer::Metallic { color, ggx } => conductor_brdf(ggx, &|_| *color, wo, wi),
+            BaseLayer::Conductor {
+                eta,
+                k,
+                ggx,
+                thin_film,
+                outside,
+            } => {
+                let fresnel = BaseLayer::conductor_fresnel(eta, k, thin_film, *outside);
+                conductor_brdf(ggx, &fresnel, wo, wi)
+            }
+            BaseLayer::Principled { lobes } => lobes.reflection(wo, wi),
+        }
+    }
+
+    // Samples a direction back up into the coat, the weight is the BSDF times the cosine
+    // divided by the pdf. Light transmitted through the base is lost.
+    fn sample(&self, wo: &Vector3<f32>) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        match self {
+            BaseLayer::Diffuse { albedo } => Some((sample_cosine_hemisphere(), *albedo)),
+            BaseLayer::OrenNayar { albedo, model } => {
+                let wi = sample_cosine_hemisphere();
+                Some((wi, albedo * model.evaluate(wo, &wi)))
+            }
+            BaseLayer::Metallic { color, ggx } => sample_conductor(ggx, &|_| *color, wo),
+            BaseLayer::Conductor {
+                eta,
+                k,
+                ggx,
+                thin_film,
+                outside,
+            } => {
+                let fresnel = BaseLayer::conductor_fresnel(eta, k, thin_film, *outside);
+                sample_conductor(ggx, &fresnel, wo)
+            }
+            BaseLayer::Principled { lobes } => match lobes.sample(wo, true)? {
+                (_, _, true) => None,
+                (wi, weight, false) => Some((wi, weight)),
+            },
+        }
+    }
+}
+
+// Mirrors a local direction to the frame seen from below the coat, with the normal along -z.
+#[inline]
+fn flip(w: &Vector3<f32>) -> Vector3<f32> {
+    vec3(w.x, w.y, -w.z)
+}
+
+// A coat over a base, evaluated by stochastic layering: light is followed through the coat
+// and bounced between its two interfaces, so all energy the coat reflects back down reaches
+// the base again instead of being lost. Directions are local, outside the coat.
+pub struct LayeredBsdf<'a> {
+    coat: &'a Coat,
+    base: BaseLayer,
+    ggx: Ggx,
+}
+
+impl<'a> LayeredBsdf<'a> {
+    pub fn new(coat: &'a Coat, base: BaseLayer) -> Self {
+        LayeredBsdf {
+            coat,
+            base,
+            ggx: Ggx::from_roughness(coat.roughness, 0.0),
+        }
+    }
+
+    // Refracts `wo` through the coat into its interior, with the weight of the transmission.
+    fn enter(&self, wo: &Vector3<f32>) -> Option<(Vector3<f32>, f32)> {
+        let h = self.ggx.sample_visible_normal(wo);
+        let inside = refract_local(wo, &h, self.coat.refractive_index)?;
+        if inside.z >= 0.0 {
+            return None;
+        }
+        let transmittance = 1.0 - fresnel_dielectric(wo.dot(h), self.coat.refractive_index);
+        let weight = transmittance * self.ggx.masking_shadowing(wo, &inside) / self.ggx.masking(wo);
+        Some((inside, weight))
+    }
+
+    // Reflection off the top of the coat, without the cosine term.
+    fn coat_reflection(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        let h = (wo + wi).normalize();
+        fresnel_dielectric(wo.dot(h), self.coat.refractive_index)
+            * self.ggx.distribution(&h)
+            * self.ggx.masking_shadowing(wo, wi)
+            / (4.0 * wo.z * wi.z)
+    }
+
+    // Stochastic estimate of the BSDF without the cosine term. Every bounce off the base
+    // connects to `wi` through the coat, treating the exit as a smooth interface.
+    pub fn evaluate(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> Vector3<f32> {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+        let eta = self.coat.refractive_index;
+        let coat = self.coat_reflection(wo, wi);
+
+        // Direction towards `wi` inside the coat, and the factor of leaving through it. The
+        // division by eta squared accounts for the compression of solid angle on refraction.
+        let sin2 = (1.0 - wi.z * wi.z) / (eta * eta);
+        let wi_inside = vec3(wi.x / eta, wi.y / eta, (1.0 - sin2).max(0.0).sqrt());
+        let exit = self.coat.attenuation(wi_inside.z)
+            * ((1.0 - fresnel_dielectric(wi.z, eta)) / (eta * eta));
+
+        let (mut down, weight) = match self.enter(wo) {
+            Some(entered) => entered,
+            None => return vec3(coat, coat, coat),
+        };
+        let mut throughput = vec3(weight, weight, weight);
+        let mut estimate = vec3(0.0, 0.0, 0.0);
+
+        for _ in 0..MAX_LAYER_BOUNCES {
+            throughput = throughput.mul_element_wise(self.coat.attenuation(down.z));
+            let up_from_base = -down;
+            estimate += throughput
+                .mul_element_wise(self.base.evaluate(&up_from_base, &wi_inside))
+                .mul_element_wise(exit);
+
+            let (up, weight) = match self.base.sample(&up_from_base) {
+                Some(sampled) => sampled,
+                None => break,
+            };
+            throughput = throughput
+                .mul_element_wise(weight)
+                .mul_element_wise(self.coat.attenuation(up.z));
+
+            // Light leaving through the top is accounted for by the connections above.
+            match sample_dielectric(&self.ggx, 1.0 / eta, &flip(&-up)) {
+                Some((w, weight, false)) => {
+                    down = flip(&w);
+                    throughput *= weight;
+                }
+                _ => break,
+            }
+        }
+        estimate + vec3(coat, coat, coat)
+    }
+
+    // Samples an outgoing local direction by following light through the layers, the weight
+    // is the BSDF times the cosine divided by the pdf.
+    pub fn sample(&self, wo: &Vector3<f32>) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let eta = self.coat.refractive_index;
+        let (down, weight, transmitted) = sample_dielectric(&self.ggx, eta, wo)?;
+        if !transmitted {
+            return Some((down, vec3(weight, weight, weight)));
+        }
+
+        let mut down = down;
+        let mut throughput = vec3(weight, weight, weight);
+        for _ in 0..MAX_LAYER_BOUNCES {
+            throughput = throughput.mul_element_wise(self.coat.attenuation(down.z));
+            let (up, weight) = self.base.sample(&-down)?;
+            throughput = throughput
+                .mul_element_wise(weight)
+                .mul_element_wise(self.coat.attenuation(up.z));
+
+            let (w, weight, transmitted) = sample_dielectric(&self.ggx, 1.0 / eta, &flip(&-up))?;
+            throughput *= weight;
+            if transmitted {
+                return Some((flip(&w), throughput));
+            }
+            down = flip(&w);
+
+            // Russian roulette once the light has bounced inside for a while.
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(1.0);
+            if survival < 0.1 {
+                if random::<f32>() >= survival {
+                    return None;
+                }
+                throughput /= survival;
+            }
+        }
+        None
+    }
+
+    // Mirror reflectance of the coat, used where the whole layer is approximated.
+    pub fn coat_reflectance(&self, cosine: f32) -> f32 {
+        fresnel_dielectric(cosine, self.coat.refractive_index)
+    }
+}
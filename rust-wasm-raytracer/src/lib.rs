@@ -1,17 +1,36 @@
 extern crate cfg_if;
 extern crate wasm_bindgen;
 
+mod aabb;
+mod csg;
+mod environment;
 mod geometric_objects;
+mod heightfield;
+mod lights;
+mod image;
+mod integrators;
+mod layered;
 mod camera;
 mod materials;
+mod media;
+mod microfacet;
+mod noise;
+mod principled;
+mod procedural;
 mod ray;
 mod scene;
+mod sdf;
 mod shade_record;
+mod sky;
+mod spectrum;
+mod subsurface;
+mod surface_detail;
+mod thin_film;
 mod world;
 
 use std::mem;
 use cfg_if::cfg_if;
-use cgmath::{InnerSpace, Point3, vec3, Vector3, VectorSpace};
+use cgmath::{ElementWise, InnerSpace, Point3, vec3, Vector3, VectorSpace};
 use wasm_bindgen::prelude::*;
 
 cfg_if! {
@@ -43,12 +62,25 @@ pub fn greet(name: &str) {
     alert(&format!("Hello,{}!", name));
 }
 
+use crate::integrators::{albedo_color, ambient_occlusion, normals_color, whitted_color};
+use crate::layered::{BaseLayer, LayeredBsdf};
 use crate::materials::{
-    generate_reflect_probability, random_vec_in_unit_sphere, reflected_vector, refracted_vector,
-    Material::*,
+    beer_lambert, generate_reflect_probability, random_vec_in_unit_sphere, reflected_vector,
+    refracted_vector, Material::*,
+};
+use crate::microfacet::{
+    conductor_brdf, fresnel_conductor, sample_conductor, sample_cosine_hemisphere,
+    sample_dielectric, Frame, Ggx, OrenNayarBrdf,
 };
 use crate::ray::Ray;
-use crate::scene::{get_predefined_scene, get_random_scene};
+use crate::shade_record::ShadeRecord;
+use crate::spectrum::{SampledWavelengths, REFERENCE_WAVELENGTH};
+use crate::subsurface::{random_walk, ScatteringMedium};
+use crate::thin_film::{ThinFilm, RGB_WAVELENGTHS};
+use crate::scene::{
+    get_material_gallery, get_predefined_scene, get_random_scene, get_shapes_scene,
+    get_showcase_scene, get_texture_gallery, SceneSettings,
+};
 use crate::world::World;
 
 fn make_random_array(len: usize) -> Vec<f32> {
@@ -78,27 +110,227 @@ fn jittered_samples(num_samples: u8) -> Vec<(f32, f32)> {
 
 const BACKGROUND_COLOR: Vector3<f32> = vec3(0.01, 0.01, 0.01);
 
-fn generate_color_for_pixel(ray: &Ray, world: &World, depth: usize) -> Vector3<f32> {
-    let shade_record = world.trace(ray);
-
-    let pixel_color: Vector3<f32> = match (shade_record, depth < 100) {
-        (_, false) => BACKGROUND_COLOR,
-        (None, _) => {
+pub(crate) fn background_color(ray: &Ray, world: &World, environment_sampled: bool) -> Vector3<f32> {
+    match (world.environment(), world.sky()) {
+        (Some(_), _) if environment_sampled => vec3(0.0, 0.0, 0.0),
+        (Some(environment), _) => environment.radiance(&ray.direction),
+        (None, Some(sky)) => sky.radiance(&ray.direction, !environment_sampled),
+        (None, None) => {
             // This code adds background ambiental fake light source.
             let unit_direction = ray.direction.normalize();
             let t = (unit_direction.y + 1.0) * 0.5;
             vec3(0.1, 0.1, 0.1).lerp(BACKGROUND_COLOR, t)
         }
+    }
+}
+
+// Splits a ray hitting a dielectric into its reflected and refracted directions, together
+// with the probability of reflection. Total internal reflection has a probability of one.
+pub(crate) fn dielectric_scatter(
+    ray: &Ray,
+    normal: &Vector3<f32>,
+    refractive_index: f32,
+) -> (Vector3<f32>, Vector3<f32>, f32) {
+    let reflected = reflected_vector(&ray.direction, normal);
+    let ni_over_t;
+    let outward_normal;
+    let mut cosine;
+
+    if ray.direction.dot(*normal) > 0.0 {
+        outward_normal = -*normal;
+        ni_over_t = refractive_index;
+        cosine = ray.direction.dot(*normal) / ray.direction.magnitude();
+        cosine = (1.0 - refractive_index * refractive_index * (1.0 - cosine * cosine)).sqrt();
+    } else {
+        outward_normal = *normal;
+        ni_over_t = 1.0 / refractive_index;
+        cosine = -ray.direction.dot(*normal) / ray.direction.magnitude();
+    }
+
+    match refracted_vector(&ray.direction, &outward_normal, ni_over_t) {
+        Some(refracted) => (
+            reflected,
+            refracted,
+            generate_reflect_probability(cosine, refractive_index),
+        ),
+        None => (reflected, vec3(1.0, 1.0, 1.0), 1.0),
+    }
+}
+
+// Reflectance of a thin film on a dielectric at the path wavelengths, or at representative
+// RGB wavelengths outside of the spectral mode.
+pub(crate) fn film_reflectance(
+    ray: &Ray,
+    normal: &Vector3<f32>,
+    film: &ThinFilm,
+    refractive_index: f32,
+    wavelengths: Option<SampledWavelengths>,
+) -> Vector3<f32> {
+    let cosine = ray.direction.normalize().dot(*normal);
+    // Light arriving from inside the dielectric sees the outside as the substrate.
+    let (outside, substrate) = if cosine < 0.0 {
+        (1.0, refractive_index)
+    } else {
+        (refractive_index, 1.0)
+    };
+    let lambdas = wavelengths.map_or(RGB_WAVELENGTHS, |w| w.lambda);
+    let substrate = vec3(substrate, substrate, substrate);
+    film.reflectance3(cosine.abs(), outside, &substrate, &vec3(0.0, 0.0, 0.0), &lambdas)
+}
+
+// Direct lighting from the background, using a shadow ray towards an importance sampled
+// direction of the environment map or towards the sun of the sky model. `brdf` is evaluated
+// per color channel for the sampled direction.
+pub(crate) fn sample_background_light<F>(rec: &ShadeRecord, world: &World, time: f32, brdf: F) -> Vector3<f32>
+where
+    F: Fn(&Vector3<f32>) -> Vector3<f32>,
+{
+    let (direction, radiance, pdf) = match (world.environment(), world.sky()) {
+        (Some(environment), _) => environment.sample(),
+        (None, Some(sky)) => sky.sample_sun(),
+        (None, None) => return vec3(0.0, 0.0, 0.0),
+    };
+    let cosine = direction.dot(rec.normal);
+    // Directions below the real surface are blocked even if a bump map tilts the normal.
+    if cosine <= 0.0 || pdf <= 0.0 || direction.dot(rec.geometric_normal) <= 0.0 {
+        return vec3(0.0, 0.0, 0.0);
+    }
+
+    let shadow_ray = Ray::new(rec.local_hit_point, direction, time);
+    if world.occluded(&shadow_ray, f32::MAX) {
+        vec3(0.0, 0.0, 0.0)
+    } else {
+        radiance.mul_element_wise(brdf(&direction))
+            * (cosine * world.transmittance(&shadow_ray, f32::MAX) / pdf)
+    }
+}
+
+// Phong exponent of the lobe used to light metals with punctual lights, it roughly matches
+// the spread of the fuzzy reflection.
+pub(crate) const METALLIC_LOBE_EXPONENT: f32 = 8.0;
+
+// Reflected radiance from the punctual lights of the world, `brdf` is evaluated per color
+// channel for the normalized direction towards each light.
+pub(crate) fn punctual_lighting<F>(rec: &ShadeRecord, world: &World, time: f32, brdf: F) -> Vector3<f32>
+where
+    F: Fn(&Vector3<f32>) -> Vector3<f32>,
+{
+    world
+        .lights()
+        .iter()
+        .fold(vec3(0.0, 0.0, 0.0), |acc, light| {
+            let (to_light, irradiance, distance) = light.illuminate(&rec.local_hit_point);
+            let cosine = to_light.dot(rec.normal);
+            let below_surface = to_light.dot(rec.geometric_normal) <= 0.0;
+            if cosine <= 0.0 || below_surface || irradiance == vec3(0.0, 0.0, 0.0) {
+                return acc;
+            }
+
+            let shadow_ray = Ray::new(rec.local_hit_point, to_light, time);
+            if world.occluded(&shadow_ray, distance) {
+                acc
+            } else {
+                let transmittance = world.transmittance(&shadow_ray, distance);
+                acc + irradiance.mul_element_wise(brdf(&to_light)) * (cosine * transmittance)
+            }
+        })
+}
+
+// `environment_sampled` is set for rays bounced off surfaces that already received direct
+// lighting from the environment map or the sun, so it isn't counted twice when they escape.
+// `absorption` is the absorption coefficient of the glass the ray currently travels through,
+// zero outside of any absorbing glass. With `wavelengths` set the returned vector holds the
+// radiance at the sampled wavelengths instead of RGB.
+fn generate_color_for_pixel(
+    ray: &Ray,
+    world: &World,
+    depth: usize,
+    environment_sampled: bool,
+    absorption: Vector3<f32>,
+    wavelengths: Option<SampledWavelengths>,
+) -> Vector3<f32> {
+    let shade_record = world.trace(ray);
+    // Colors are given in RGB, in the spectral mode they are upsampled at the path wavelengths.
+    let spectrum = |rgb: Vector3<f32>| wavelengths.map_or(rgb, |w| w.upsample(&rgb));
+    let segment_transmittance =
+        |t: f32| spectrum(beer_lambert(&absorption, t * ray.direction.magnitude()));
+
+    if depth < 100 {
+        let t_surface = shade_record
+            .as_ref()
+            .map_or(f32::MAX, |rec| rec.intersect_parameter);
+
+        // A real collision inside a participating medium happens before the surface is reached.
+        if let Some(interaction) = world.sample_media(ray, t_surface) {
+            let medium = interaction.medium;
+            let scattered = Ray::new(
+                interaction.point,
+                medium.phase.sample(&ray.direction),
+                ray.time,
+            );
+            let v = generate_color_for_pixel(
+                &scattered,
+                world,
+                depth + 1,
+                false,
+                absorption,
+                wavelengths,
+            );
+            return v
+                .mul_element_wise(spectrum(medium.albedo))
+                .mul_element_wise(segment_transmittance(interaction.intersect_parameter));
+        }
+    }
+
+    let pixel_color: Vector3<f32> = match (shade_record, depth < 100) {
+        (_, false) => spectrum(BACKGROUND_COLOR),
+        (None, _) => spectrum(background_color(ray, world, environment_sampled)),
         // TODO: Figure out how to add time=0.0 as default param for ray class
         (Some(ref rec), true) => {
             let accumulated_color: Vector3<f32> = match &rec.material {
                 Lambertian { texture } => {
-                    let target = rec.local_hit_point + rec.normal + random_vec_in_unit_sphere();
-                    let bounced_ray =
-                        Ray::new(rec.local_hit_point, target - rec.local_hit_point, 0.0);
-                    let v = generate_color_for_pixel(&bounced_ray, world, depth + 1);
-                    let Point3 { x: r, y: g, z: b } = texture.value(0.0, 0.0, &rec.local_hit_point);
-                    vec3(v.x * r, v.y * g, v.z * b)
+                    let direction =
+                        Frame::from_normal(&rec.normal).to_world(&sample_cosine_hemisphere());
+                    let bounced_ray = Ray::new(rec.local_hit_point, direction, ray.time);
+                    let lambert = |_: &Vector3<f32>| vec3(1.0, 1.0, 1.0) / std::f32::consts::PI;
+                    let direct = sample_background_light(rec, world, ray.time, lambert)
+                        + punctual_lighting(rec, world, ray.time, lambert);
+                    let v = generate_color_for_pixel(
+                        &bounced_ray,
+                        world,
+                        depth + 1,
+                        true,
+                        absorption,
+                        wavelengths,
+                    ) + spectrum(direct);
+                    let Point3 { x: r, y: g, z: b } = texture.value(rec.u, rec.v, &rec.object_hit_point);
+                    v.mul_element_wise(spectrum(vec3(r, g, b)))
+                }
+                OrenNayar { texture, sigma } => {
+                    let model = OrenNayarBrdf::new(*sigma);
+                    let frame = Frame::from_normal(&rec.normal);
+                    let wo = frame.to_local(&-ray.direction.normalize());
+                    let brdf = |to_light: &Vector3<f32>| {
+                        let f = model.evaluate(&wo, &frame.to_local(to_light)) / std::f32::consts::PI;
+                        vec3(f, f, f)
+                    };
+                    let direct = sample_background_light(rec, world, ray.time, brdf)
+                        + punctual_lighting(rec, world, ray.time, brdf);
+
+                    // Cosine weighted sampling leaves the model relative to Lambert as weight.
+                    let wi = sample_cosine_hemisphere();
+                    let bounced_ray = Ray::new(rec.local_hit_point, frame.to_world(&wi), ray.time);
+                    let v = generate_color_for_pixel(
+                        &bounced_ray,
+                        world,
+                        depth + 1,
+                        true,
+                        absorption,
+                        wavelengths,
+                    ) * model.evaluate(&wo, &wi)
+                        + spectrum(direct);
+                    let Point3 { x: r, y: g, z: b } = texture.value(rec.u, rec.v, &rec.object_hit_point);
+                    v.mul_element_wise(spectrum(vec3(r, g, b)))
                 }
                 Metallic { r, g, b } => {
                     let reflected = reflected_vector(&ray.direction.normalize(), &rec.normal);
@@ -108,81 +340,411 @@ fn generate_color_for_pixel(ray: &Ray, world: &World, depth: usize) -> Vector3<f
                         0.0,
                     );
 
-                    if scattered.direction.dot(rec.normal) > 0.0 {
-                        let u = generate_color_for_pixel(&scattered, world, depth + 1);
-                        vec3(u.x * r, u.y * g, u.z * b)
+                    let direct = punctual_lighting(rec, world, ray.time, |to_light| {
+                        let alignment = to_light.dot(reflected).max(0.0);
+                        vec3(1.0, 1.0, 1.0) * (METALLIC_LOBE_EXPONENT + 2.0)
+                            / (2.0 * std::f32::consts::PI)
+                            * alignment.powf(METALLIC_LOBE_EXPONENT)
+                    });
+                    let tint = spectrum(vec3(*r, *g, *b));
+                    let u = generate_color_for_pixel(
+                        &scattered,
+                        world,
+                        depth + 1,
+                        false,
+                        absorption,
+                        wavelengths,
+                    );
+                    let indirect = if scattered.direction.dot(rec.normal) > 0.0 {
+                        u.mul_element_wise(tint)
                     } else {
-                        generate_color_for_pixel(&scattered, world, depth + 1)
-                    }
+                        u
+                    };
+                    indirect + direct.mul_element_wise(tint)
+                }
+                Dielectric {
+                    refractive_index,
+                    thin_film: None,
+                } => {
+                    let (reflected, refracted, reflect_prob) =
+                        dielectric_scatter(ray, &rec.normal, *refractive_index);
+
+                    // Clear glass, a refracted ray leaves any absorbing glass it was inside of.
+                    let (bounced_ray, interior) = if random() < reflect_prob {
+                        (Ray::new(rec.local_hit_point, reflected, 0.0), absorption)
+                    } else {
+                        (Ray::new(rec.local_hit_point, refracted, 0.0), vec3(0.0, 0.0, 0.0))
+                    };
+                    generate_color_for_pixel(
+                        &bounced_ray,
+                        world,
+                        depth + 1,
+                        false,
+                        interior,
+                        wavelengths,
+                    )
+                }
+                Dielectric {
+                    refractive_index,
+                    thin_film: Some(film),
+                } => {
+                    let (reflected, refracted, reflect_prob) =
+                        dielectric_scatter(ray, &rec.normal, *refractive_index);
+                    let reflectance =
+                        film_reflectance(ray, &rec.normal, film, *refractive_index, wavelengths);
+
+                    // The film reflects each wavelength differently, the branch is chosen by
+                    // the average reflectance and the weights correct for it.
+                    let probability = ((reflectance.x + reflectance.y + reflectance.z) / 3.0)
+                        .clamp(0.01, 0.99);
+                    let (bounced_ray, interior, weight) = if reflect_prob >= 1.0 {
+                        let ray = Ray::new(rec.local_hit_point, reflected, ray.time);
+                        (ray, absorption, vec3(1.0, 1.0, 1.0))
+                    } else if random() < probability {
+                        let ray = Ray::new(rec.local_hit_point, reflected, ray.time);
+                        (ray, absorption, reflectance / probability)
+                    } else {
+                        let ray = Ray::new(rec.local_hit_point, refracted, ray.time);
+                        let transmittance = vec3(1.0, 1.0, 1.0) - reflectance;
+                        (ray, vec3(0.0, 0.0, 0.0), transmittance / (1.0 - probability))
+                    };
+                    generate_color_for_pixel(
+                        &bounced_ray,
+                        world,
+                        depth + 1,
+                        false,
+                        interior,
+                        wavelengths,
+                    )
+                    .mul_element_wise(weight)
                 }
-                Dielectric { refractive_index } => {
-                    let reflected = reflected_vector(&ray.direction, &rec.normal);
-                    let ni_over_t;
-                    let outward_normal;
-                    let refracted;
-                    let reflect_prob;
-                    let mut cosine;
-
-                    if ray.direction.dot(rec.normal) > 0.0 {
-                        outward_normal = -rec.normal;
-                        ni_over_t = *refractive_index;
-                        cosine = ray.direction.dot(rec.normal) / ray.direction.magnitude();
-                        cosine = (1.0
-                            - refractive_index * refractive_index * (1.0 - cosine * cosine))
-                            .sqrt();
+                DispersiveDielectric { refractive_index } => {
+                    let mut wavelengths = wavelengths;
+                    let wavelength = wavelengths.map_or(REFERENCE_WAVELENGTH, |w| w.hero());
+                    let (reflected, refracted, reflect_prob) =
+                        dielectric_scatter(ray, &rec.normal, refractive_index.at(wavelength));
+
+                    let (bounced_ray, interior, weight) = if random() < reflect_prob {
+                        // The secondary wavelengths follow the reflection with the probability
+                        // of the hero, weighted by their own reflectance.
+                        let weight = match wavelengths {
+                            Some(w) if refractive_index.is_dispersive() && !w.hero_only => {
+                                let reflectance = |lambda: f32| {
+                                    dielectric_scatter(ray, &rec.normal, refractive_index.at(lambda)).2
+                                };
+                                vec3(reflect_prob, reflectance(w.lambda.y), reflectance(w.lambda.z))
+                                    / reflect_prob
+                            }
+                            _ => vec3(1.0, 1.0, 1.0),
+                        };
+                        let bounced_ray = Ray::new(rec.local_hit_point, reflected, ray.time);
+                        (bounced_ray, absorption, weight)
+                    } else {
+                        // Only the hero wavelength follows a refracted direction that depends
+                        // on it, the other samples of the path are dropped before recursing.
+                        let weight = match wavelengths.as_mut() {
+                            Some(w) if refractive_index.is_dispersive() => w.terminate_secondary(),
+                            _ => vec3(1.0, 1.0, 1.0),
+                        };
+                        let bounced_ray = Ray::new(rec.local_hit_point, refracted, ray.time);
+                        (bounced_ray, vec3(0.0, 0.0, 0.0), weight)
+                    };
+                    generate_color_for_pixel(
+                        &bounced_ray,
+                        world,
+                        depth + 1,
+                        false,
+                        interior,
+                        wavelengths,
+                    )
+                    .mul_element_wise(weight)
+                }
+                DiffuseLight { .. } => spectrum(rec.material.emitted(rec, &ray.direction)),
+                Subsurface {
+                    refractive_index,
+                    albedo,
+                    mean_free_path,
+                    anisotropy,
+                } => {
+                    let (reflected, refracted, reflect_prob) =
+                        dielectric_scatter(ray, &rec.normal, *refractive_index);
+                    if random() < reflect_prob {
+                        let bounced_ray = Ray::new(rec.local_hit_point, reflected, ray.time);
+                        generate_color_for_pixel(
+                            &bounced_ray,
+                            world,
+                            depth + 1,
+                            false,
+                            absorption,
+                            wavelengths,
+                        )
                     } else {
-                        outward_normal = rec.normal;
-                        ni_over_t = 1.0 / refractive_index;
-                        cosine = -ray.direction.dot(rec.normal) / ray.direction.magnitude();
+                        let medium = ScatteringMedium::from_mean_free_path(
+                            &spectrum(*mean_free_path),
+                            &spectrum(*albedo),
+                            &spectrum(*anisotropy),
+                        );
+                        let entry = Ray::new(rec.local_hit_point, refracted, ray.time);
+                        match random_walk(world, &entry, &medium, *refractive_index) {
+                            // Light leaves diffusely where the walk crosses the boundary.
+                            Some((exit, throughput)) => {
+                                let direction = Frame::from_normal(&exit.normal)
+                                    .to_world(&sample_cosine_hemisphere());
+                                let bounced_ray =
+                                    Ray::new(exit.local_hit_point, direction, ray.time);
+                                let lambert =
+                                    |_: &Vector3<f32>| vec3(1.0, 1.0, 1.0) / std::f32::consts::PI;
+                                let direct = sample_background_light(&exit, world, ray.time, lambert)
+                                    + punctual_lighting(&exit, world, ray.time, lambert);
+                                let v = generate_color_for_pixel(
+                                    &bounced_ray,
+                                    world,
+                                    depth + 1,
+                                    true,
+                                    absorption,
+                                    wavelengths,
+                                ) + spectrum(direct);
+                                v.mul_element_wise(throughput)
+                            }
+                            None => vec3(0.0, 0.0, 0.0),
+                        }
                     }
+                }
+                Masked { .. } | Mix { .. } => {
+                    unreachable!("masks and mixes are resolved by World::trace")
+                }
+                Conductor {
+                    eta,
+                    k,
+                    roughness,
+                    anisotropy,
+                    thin_film,
+                } => {
+                    // Conductors are opaque, shade the side the ray arrived from.
+                    let normal = if ray.direction.dot(rec.normal) > 0.0 {
+                        -rec.normal
+                    } else {
+                        rec.normal
+                    };
+                    let frame = Frame::new(&normal, &rec.tangent);
+                    let ggx = Ggx::from_roughness(*roughness, *anisotropy);
+                    let wo = frame.to_local(&-ray.direction.normalize());
 
-                    if let Some(x) = refracted_vector(&ray.direction, &outward_normal, ni_over_t) {
-                        reflect_prob = generate_reflect_probability(cosine, *refractive_index);
-                        refracted = x;
+                    // Direct light is computed in RGB, the bounce directly at the path
+                    // wavelengths with upsampled optical constants.
+                    let (spectral_eta, spectral_k) = (spectrum(*eta), spectrum(*k));
+                    let lambdas = wavelengths.map_or(RGB_WAVELENGTHS, |w| w.lambda);
+                    let fresnel = |cosine: f32, eta, k, lambdas| match thin_film {
+                        Some(film) => film.reflectance3(cosine, 1.0, eta, k, lambdas),
+                        None => fresnel_conductor(cosine, eta, k),
+                    };
+
+                    let direct = punctual_lighting(rec, world, ray.time, |to_light| {
+                        let fresnel = |cosine| fresnel(cosine, eta, k, &RGB_WAVELENGTHS);
+                        conductor_brdf(&ggx, &fresnel, &wo, &frame.to_local(to_light))
+                    });
+                    let path_fresnel =
+                        |cosine| fresnel(cosine, &spectral_eta, &spectral_k, &lambdas);
+                    let indirect = match sample_conductor(&ggx, &path_fresnel, &wo) {
+                        Some((wi, weight)) => {
+                            let scattered =
+                                Ray::new(rec.local_hit_point, frame.to_world(&wi), ray.time);
+                            let v = generate_color_for_pixel(
+                                &scattered,
+                                world,
+                                depth + 1,
+                                false,
+                                absorption,
+                                wavelengths,
+                            );
+                            v.mul_element_wise(weight)
+                        }
+                        None => vec3(0.0, 0.0, 0.0),
+                    };
+                    spectrum(direct) + indirect
+                }
+                Glass {
+                    refractive_index,
+                    roughness,
+                    absorption: glass_absorption,
+                } => {
+                    let entering = ray.direction.dot(rec.normal) < 0.0;
+                    let (normal, eta) = if entering {
+                        (rec.normal, *refractive_index)
                     } else {
-                        reflect_prob = 1.0;
-                        refracted = vec3(1.0, 1.0, 1.0);
+                        (-rec.normal, 1.0 / refractive_index)
                     };
+                    let frame = Frame::new(&normal, &rec.tangent);
+                    let ggx = Ggx::from_roughness(*roughness, 0.0);
+                    let wo = frame.to_local(&-ray.direction.normalize());
 
-                    let bounced_ray = if random() < reflect_prob {
-                        Ray::new(rec.local_hit_point, reflected, 0.0)
+                    match sample_dielectric(&ggx, eta, &wo) {
+                        Some((wi, weight, transmitted)) => {
+                            let interior = match (transmitted, entering) {
+                                (false, _) => absorption,
+                                (true, true) => *glass_absorption,
+                                (true, false) => vec3(0.0, 0.0, 0.0),
+                            };
+                            let bounced_ray =
+                                Ray::new(rec.local_hit_point, frame.to_world(&wi), ray.time);
+                            let v = generate_color_for_pixel(
+                                &bounced_ray,
+                                world,
+                                depth + 1,
+                                false,
+                                interior,
+                                wavelengths,
+                            );
+                            v * weight
+                        }
+                        None => vec3(0.0, 0.0, 0.0),
+                    }
+                }
+                Coated { coat, base } => {
+                    // Coated materials are opaque, shade the side the ray arrived from.
+                    let normal = if ray.direction.dot(rec.normal) > 0.0 {
+                        -rec.normal
                     } else {
-                        Ray::new(rec.local_hit_point, refracted, 0.0)
+                        rec.normal
                     };
-                    generate_color_for_pixel(&bounced_ray, world, depth + 1)
+                    let frame = Frame::new(&normal, &rec.tangent);
+                    let base =
+                        BaseLayer::from_material(base, coat, rec.u, rec.v, &rec.object_hit_point);
+                    let layers = LayeredBsdf::new(coat, base);
+                    let wo = frame.to_local(&-ray.direction.normalize());
+
+                    let direct = punctual_lighting(rec, world, ray.time, |to_light| {
+                        layers.evaluate(&wo, &frame.to_local(to_light))
+                    });
+                    let indirect = match layers.sample(&wo) {
+                        Some((wi, weight)) => {
+                            let bounced_ray =
+                                Ray::new(rec.local_hit_point, frame.to_world(&wi), ray.time);
+                            let v = generate_color_for_pixel(
+                                &bounced_ray,
+                                world,
+                                depth + 1,
+                                false,
+                                absorption,
+                                wavelengths,
+                            );
+                            v.mul_element_wise(spectrum(weight))
+                        }
+                        None => vec3(0.0, 0.0, 0.0),
+                    };
+                    spectrum(direct) + indirect
                 }
-                DiffuseLight { texture } => {
-                    let Point3 { x: r, y: g, z: b } = texture.value(
-                        rec.local_hit_point.x,
-                        rec.local_hit_point.y,
-                        &rec.local_hit_point,
-                    );
-                    vec3(r, g, b)
+                Principled { bsdf } => {
+                    let entering = ray.direction.dot(rec.normal) < 0.0;
+                    let normal = if entering { rec.normal } else { -rec.normal };
+                    let frame = Frame::new(&normal, &rec.tangent);
+                    let lobes = bsdf.evaluate(rec.u, rec.v, &rec.object_hit_point);
+                    let wo = frame.to_local(&-ray.direction.normalize());
+
+                    let direct = punctual_lighting(rec, world, ray.time, |to_light| {
+                        lobes.reflection(&wo, &frame.to_local(to_light))
+                    });
+                    let indirect = match lobes.sample(&wo, entering) {
+                        Some((wi, weight, transmitted)) => {
+                            let interior = if transmitted {
+                                vec3(0.0, 0.0, 0.0)
+                            } else {
+                                absorption
+                            };
+                            let bounced_ray =
+                                Ray::new(rec.local_hit_point, frame.to_world(&wi), ray.time);
+                            let v = generate_color_for_pixel(
+                                &bounced_ray,
+                                world,
+                                depth + 1,
+                                false,
+                                interior,
+                                wavelengths,
+                            );
+                            v.mul_element_wise(spectrum(weight))
+                        }
+                        None => vec3(0.0, 0.0, 0.0),
+                    };
+                    spectrum(direct) + indirect
                 }
             };
-            accumulated_color
+            accumulated_color.mul_element_wise(segment_transmittance(rec.intersect_parameter))
         }
     };
     pixel_color
 }
 
 #[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub enum RenderMode {
+    PathTracing,
+    Whitted,
+    AmbientOcclusion,
+    Normals,
+    Albedo,
+    Spectral,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy)]
+pub enum SceneType {
+    Predefined,
+    Random,
+    Showcase,
+    Shapes,
+    Materials,
+    Textures,
+}
+
+fn render_sample(ray: &Ray, world: &World, render_mode: RenderMode, occlusion_radius: f32) -> Vector3<f32> {
+    match render_mode {
+        RenderMode::PathTracing => {
+            generate_color_for_pixel(ray, world, 0, false, vec3(0.0, 0.0, 0.0), None)
+        }
+        RenderMode::Spectral => {
+            let wavelengths = SampledWavelengths::sample();
+            let radiance = generate_color_for_pixel(
+                ray,
+                world,
+                0,
+                false,
+                vec3(0.0, 0.0, 0.0),
+                Some(wavelengths),
+            );
+            wavelengths.to_rgb(&radiance)
+        }
+        RenderMode::Whitted => whitted_color(ray, world, 0),
+        RenderMode::AmbientOcclusion => ambient_occlusion(ray, world, occlusion_radius),
+        RenderMode::Normals => normals_color(ray, world),
+        RenderMode::Albedo => albedo_color(ray, world),
+    }
+}
+
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
 pub fn make_image(
     canvas_width: u16,
     canvas_height: u16,
     num_samples: u8,
-    random_scene: bool,
+    scene_type: SceneType,
     jittered_sampling: bool,
+    render_mode: RenderMode,
+    occlusion_radius: f32,
+    settings: &SceneSettings,
 ) -> Vec<u32> {
     let preallocate_capacity = usize::from(canvas_width) * usize::from(canvas_height);
 
     let samples_divider = f32::from(num_samples);
 
-    let (camera, world) = if random_scene {
-        get_random_scene(canvas_width, canvas_height, 20)
-    } else {
-        get_predefined_scene(canvas_width, canvas_height)
+    let (camera, mut world) = match scene_type {
+        SceneType::Predefined => get_predefined_scene(canvas_width, canvas_height),
+        SceneType::Random => get_random_scene(canvas_width, canvas_height, 20),
+        SceneType::Showcase => get_showcase_scene(canvas_width, canvas_height, settings),
+        SceneType::Shapes => get_shapes_scene(canvas_width, canvas_height, settings),
+        SceneType::Materials => get_material_gallery(canvas_width, canvas_height),
+        SceneType::Textures => get_texture_gallery(canvas_width, canvas_height),
     };
+    settings.apply(&mut world);
     let mut pixel_color = vec3(0.0, 0.0, 0.0);
     let mut image = Vec::<u32>::with_capacity(preallocate_capacity);
 
@@ -211,7 +773,7 @@ pub fn make_image(
                 let dy = (f32::from(i) + sample.1) / f32::from(canvas_height);
 
                 let direction = camera.get_ray(dx, dy);
-                pixel_color += generate_color_for_pixel(&direction, &world, 0);
+                pixel_color += render_sample(&direction, &world, render_mode, occlusion_radius);
             }
             pixel_color /= samples_divider;
 
@@ -0,0 +1,74 @@
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3};
+use std::f32;
+
+// Delta light sources, they can only be reached through shadow rays.
+#[derive(Clone, Copy)]
+pub enum Light {
+    Point {
+        position: Point3<f32>,
+        intensity: Vector3<f32>,
+    },
+    // Full intensity inside `falloff_start`, smoothly fading to zero at `cone_angle`, in degrees.
+    Spot {
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        intensity: Vector3<f32>,
+        cone_angle: f32,
+        falloff_start: f32,
+    },
+    // `direction` is the direction the light travels in.
+    Directional {
+        direction: Vector3<f32>,
+        irradiance: Vector3<f32>,
+    },
+}
+
+impl Light {
+    // Returns the normalized direction towards the light, the irradiance it delivers to a
+    // surface facing it, and the distance to the light.
+    pub fn illuminate(&self, point: &Point3<f32>) -> (Vector3<f32>, Vector3<f32>, f32) {
+        match self {
+            Light::Point {
+                position,
+                intensity,
+            } => {
+                let to_light = position - point;
+                let distance2 = to_light.magnitude2();
+                let distance = distance2.sqrt();
+                (to_light / distance, intensity / distance2, distance)
+            }
+            Light::Spot {
+                position,
+                direction,
+                intensity,
+                cone_angle,
+                falloff_start,
+            } => {
+                let to_light = position - point;
+                let distance2 = to_light.magnitude2();
+                let distance = distance2.sqrt();
+                let to_light = to_light / distance;
+
+                let cos_theta = -to_light.dot(direction.normalize());
+                let (cos_total, cos_falloff) = (
+                    cone_angle.to_radians().cos(),
+                    falloff_start.to_radians().cos(),
+                );
+                let falloff = if cos_theta < cos_total {
+                    0.0
+                } else if cos_theta >= cos_falloff {
+                    1.0
+                } else {
+                    let delta = (cos_theta - cos_total) / (cos_falloff - cos_total);
+                    delta * delta * (3.0 - 2.0 * delta)
+                };
+                (to_light, intensity * (falloff / distance2), distance)
+            }
+            Light::Directional {
+                direction,
+                irradiance,
+            } => (-direction.normalize(), *irradiance, f32::MAX),
+        }
+    }
+}
@@ -1,11 +1,17 @@
-use arr_macro::arr;
 use cgmath::prelude::*;
-use cgmath::{vec3, Point3, Vector3};
-use lazy_static::lazy_static;
+use cgmath::{vec3, Deg, Matrix4, Point3, Vector3};
 use std::f32;
 use rand::random;
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+
+use crate::image::{Image, WrapMode};
+use crate::layered::Coat;
+use crate::microfacet::fresnel_conductor;
+use crate::noise::{NoiseBasis, Perlin};
+use crate::principled::PrincipledBsdf;
+use crate::procedural::{linear_gradient, worley, CellularFeature, ColorRamp, Fbm};
+use crate::shade_record::ShadeRecord;
+use crate::spectrum::{blackbody, RefractiveIndex};
+use crate::thin_film::{ThinFilm, RGB_WAVELENGTHS};
 
 
 #[inline]
@@ -20,6 +26,17 @@ pub fn generate_reflect_probability(cosine: f32, refractive_index: f32) -> f32 {
     r0 + ((1.0 - r0) * ((1.0 - cosine).powf(5.0)))
 }
 
+// Fraction of light left after travelling `distance` through a medium with the given
+// per channel absorption coefficient.
+#[inline]
+pub fn beer_lambert(absorption: &Vector3<f32>, distance: f32) -> Vector3<f32> {
+    vec3(
+        (-absorption.x * distance).exp(),
+        (-absorption.y * distance).exp(),
+        (-absorption.z * distance).exp(),
+    )
+}
+
 pub fn refracted_vector(
     v: &Vector3<f32>,
     n: &Vector3<f32>,
@@ -36,6 +53,8 @@ pub fn refracted_vector(
     }
 }
 
+// Uniformly distributed point inside the unit sphere. The radius is the cube root of a uniform
+// number, as the volume inside radius r grows with r^3.
 pub fn random_vec_in_unit_sphere() -> Vector3<f32> {
     let z = 1.0 - (2.0 * random::<f32>());
     let r = (1.0 - (z * z)).sqrt();
@@ -43,144 +62,289 @@ pub fn random_vec_in_unit_sphere() -> Vector3<f32> {
     let x = r * theta.cos();
     let y = r * theta.sin();
 
-    random::<f32>() * vec3(x, y, z)
+    random::<f32>().cbrt() * vec3(x, y, z)
 }
 
 pub enum Texture {
     Constant {
         color: Point3<f32>,
     },
+    // Solid checkerboard, alternating with the sign of sin(frequency * x) for each axis.
     Checkerboard {
+        frequency: f32,
         left: Box<Texture>,
         right: Box<Texture>,
     },
-    Noise,
+    // Marble-like stripes along z, kept from the original scene.
+    Noise {
+        perlin: Perlin,
+    },
+    // A single octave of noise in the chosen basis, mapped from [-1, 1] onto the ramp.
+    BasisNoise {
+        perlin: Perlin,
+        basis: NoiseBasis,
+        ramp: ColorRamp,
+    },
+    Turbulence {
+        fbm: Fbm,
+        ramp: ColorRamp,
+    },
+    // Stripes along z, bent by `distortion` times the turbulence.
+    Marble {
+        fbm: Fbm,
+        frequency: f32,
+        distortion: f32,
+        ramp: ColorRamp,
+    },
+    // Concentric rings around the y axis, `rings` per unit of distance.
+    Wood {
+        fbm: Fbm,
+        rings: f32,
+        distortion: f32,
+        ramp: ColorRamp,
+    },
+    Worley {
+        scale: f32,
+        jitter: f32,
+        seed: u32,
+        feature: CellularFeature,
+        ramp: ColorRamp,
+    },
+    Gradient {
+        from: Point3<f32>,
+        to: Point3<f32>,
+        ramp: ColorRamp,
+    },
+    UvChecker {
+        frequency_u: f32,
+        frequency_v: f32,
+        odd: Box<Texture>,
+        even: Box<Texture>,
+    },
+    // Lines of width `line_width`, as a fraction of a cell, over a fill texture.
+    UvGrid {
+        frequency_u: f32,
+        frequency_v: f32,
+        line_width: f32,
+        line: Box<Texture>,
+        fill: Box<Texture>,
+    },
+    // Linear blend from `a` to `b` by the first channel of `factor`.
+    Mix {
+        a: Box<Texture>,
+        b: Box<Texture>,
+        factor: Box<Texture>,
+    },
+    Multiply {
+        a: Box<Texture>,
+        b: Box<Texture>,
+    },
+    Transformed {
+        texture: Box<Texture>,
+        space: TextureSpace,
+        transform: TextureTransform,
+    },
+    Image {
+        image: Image,
+        wrap: WrapMode,
+    },
+    // Alpha channel of an image as a gray texture, for opacity masks.
+    Alpha {
+        image: Image,
+        wrap: WrapMode,
+    },
+}
+
+// Coordinates a transformed texture is evaluated in. Object space uses the hit point relative to
+// the object, UV space the surface parameterization as the point (u, v, 0).
+#[derive(Clone, Copy)]
+pub enum TextureSpace {
+    Object,
+    Uv,
+}
+
+// Maps shading coordinates into a texture's own space. Operations apply in the order they are
+// chained, rotations are in degrees.
+#[derive(Clone, Copy)]
+pub struct TextureTransform {
+    matrix: Matrix4<f32>,
 }
 
-pub struct Perlin {
-    pub scale_factor: f32,
-    pub random_vecs: [Vector3<f32>; 256],
-    pub random_x_direction: [i32; 256],
-    pub random_y_direction: [i32; 256],
-    pub random_z_direction: [i32; 256],
+impl Default for TextureTransform {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-// TODO: Refactor perlin implementation
-impl Perlin {
+impl TextureTransform {
     pub fn new() -> Self {
-        Perlin {
-            scale_factor: 5.0,
-            random_vecs: Perlin::perlin_generate(),
-            random_x_direction: Perlin::generate_perm(),
-            random_y_direction: Perlin::generate_perm(),
-            random_z_direction: Perlin::generate_perm(),
+        TextureTransform {
+            matrix: Matrix4::identity(),
         }
     }
 
-    #[inline]
-    pub fn perlin_generate() -> [Vector3<f32>; 256] {
-        arr![vec3(-1.0 + 2.0 * random::<f32>(), -1.0 + 2.0 * random::<f32>(), -1.0 + 2.0 * random::<f32>()).normalize(); 256]
-    }
-
-    pub fn generate_perm() -> [i32; 256] {
-        let mut i = -1_i32;
-        let mut shuffled_array = arr![{ i += 1; i}; 256];
-        let mut rng = thread_rng();
-        shuffled_array.shuffle(&mut rng);
-        shuffled_array
-    }
-
-    pub fn generate_noise(point: &Point3<f32>) -> f32 {
-        let scaled_point = point * 1.0;
-        let u = scaled_point.x - scaled_point.x.floor();
-        let v = scaled_point.y - scaled_point.y.floor();
-        let w = scaled_point.z - scaled_point.z.floor();
-
-        let i = scaled_point.x.floor() as i32;
-        let j = scaled_point.y.floor() as i32;
-        let k = scaled_point.z.floor() as i32;
-
-        let mut c: [[[Vector3<f32>; 2]; 2]; 2] = [
-            [
-                [vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0)],
-                [vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0)],
-            ],
-            [
-                [vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0)],
-                [vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0)],
-            ],
-        ];
-        for di in 0..2 {
-            for dj in 0..2 {
-                for dk in 0..2 {
-                    let x_idx = ((i + di) & 255) as usize;
-                    let y_idx = ((j + dj) & 255) as usize;
-                    let z_idx = ((k + dk) & 255) as usize;
-
-                    let rand_x = PERLIN_STATIC_REF.random_x_direction[x_idx];
-                    let rand_y = PERLIN_STATIC_REF.random_y_direction[y_idx];
-                    let rand_z = PERLIN_STATIC_REF.random_z_direction[z_idx];
-
-                    let index = (rand_x ^ rand_y ^ rand_z) as usize;
-
-                    c[di as usize][dj as usize][dk as usize] = PERLIN_STATIC_REF.random_vecs[index];
-                }
-            }
-        }
+    pub fn scaled(self, factors: Vector3<f32>) -> Self {
+        self.then(Matrix4::from_nonuniform_scale(factors.x, factors.y, factors.z))
+    }
 
-        let uu = u * u * (3.0 - 2.0 * u);
-        let vv = v * v * (3.0 - 2.0 * v);
-        let ww = w * w * (3.0 - 2.0 * w);
+    pub fn rotated(self, axis: Vector3<f32>, degrees: f32) -> Self {
+        self.then(Matrix4::from_axis_angle(axis.normalize(), Deg(degrees)))
+    }
 
-        let mut acc = 0.0;
+    // Rotation of UV coordinates around the texture origin.
+    pub fn rotated_2d(self, degrees: f32) -> Self {
+        self.rotated(vec3(0.0, 0.0, 1.0), degrees)
+    }
 
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let weight_v = vec3(u - i as f32, v - j as f32, w - k as f32);
-                    acc += (i as f32 * uu + (1.0 - i as f32) * (1.0 - uu))
-                        * (j as f32 * vv + (1.0 - j as f32) * (1.0 - vv))
-                        * (k as f32 * ww + (1.0 - k as f32) * (1.0 - ww))
-                        * weight_v.dot(c[i as usize][j as usize][k as usize]);
-                }
-            }
-        }
+    pub fn offset(self, offset: Vector3<f32>) -> Self {
+        self.then(Matrix4::from_translation(offset))
+    }
 
-        acc
+    fn then(self, matrix: Matrix4<f32>) -> Self {
+        TextureTransform {
+            matrix: matrix * self.matrix,
+        }
     }
-}
 
-lazy_static! {
-    static ref PERLIN_STATIC_REF: Perlin = Perlin::new();
+    pub fn apply(&self, point: &Point3<f32>) -> Point3<f32> {
+        self.matrix.transform_point(*point)
+    }
 }
 
 impl Texture {
-    pub fn value(&self, x: f32, y: f32, point: &Point3<f32>) -> Point3<f32> {
+    // Uniform gray texture, used for scalar material parameters.
+    pub fn scalar(value: f32) -> Self {
+        Texture::Constant {
+            color: Point3::new(value, value, value),
+        }
+    }
+
+    pub fn transformed(self, space: TextureSpace, transform: TextureTransform) -> Self {
+        Texture::Transformed {
+            texture: Box::new(self),
+            space,
+            transform,
+        }
+    }
+
+    pub fn value(&self, u: f32, v: f32, point: &Point3<f32>) -> Point3<f32> {
         match self {
             Texture::Constant { color } => *color,
-            Texture::Checkerboard { left, right } => {
-                let sines =
-                    f32::sin(10.0 * point.x) * f32::sin(10.0 * point.y) * f32::sin(10.0 * point.z);
+            Texture::Checkerboard { frequency, left, right } => {
+                let sines = f32::sin(frequency * point.x)
+                    * f32::sin(frequency * point.y)
+                    * f32::sin(frequency * point.z);
 
                 if sines < 0.0 {
-                    left.value(x, y, point)
+                    left.value(u, v, point)
                 } else {
-                    right.value(x, y, point)
+                    right.value(u, v, point)
                 }
             }
-            Texture::Noise => {
+            Texture::Noise { perlin } => {
                 let mut acc = 0.0;
                 let mut temp_p = *point;
                 let mut weight = 1.0;
                 for _i in 0..7 {
-                    acc += weight * Perlin::generate_noise(&temp_p);
+                    acc += weight * perlin.generate_noise(&temp_p);
                     weight *= 0.5;
                     temp_p *= 2.0;
                 }
 
                 Point3::new(1.0, 1.0, 1.0)
                     * 0.5
-                    * (1.0 + f32::sin(PERLIN_STATIC_REF.scale_factor * point.z + 10.0 * acc))
+                    * (1.0 + f32::sin(5.0 * point.z + 10.0 * acc))
+            }
+            Texture::BasisNoise {
+                perlin,
+                basis,
+                ramp,
+            } => ramp.value(0.5 * (1.0 + perlin.sample(*basis, u, v, point))),
+            Texture::Turbulence { fbm, ramp } => ramp.value(fbm.turbulence(point)),
+            Texture::Marble {
+                fbm,
+                frequency,
+                distortion,
+                ramp,
+            } => {
+                let phase = frequency * point.z + distortion * fbm.turbulence(point);
+                ramp.value(0.5 * (1.0 + phase.sin()))
+            }
+            Texture::Wood {
+                fbm,
+                rings,
+                distortion,
+                ramp,
+            } => {
+                let radius = (point.x * point.x + point.z * point.z).sqrt();
+                let ring = (radius + distortion * fbm.noise(point)) * rings;
+                ramp.value(ring - ring.floor())
+            }
+            Texture::Worley {
+                scale,
+                jitter,
+                seed,
+                feature,
+                ramp,
+            } => ramp.value(feature.select(worley(&(point * *scale), *jitter, *seed))),
+            Texture::Gradient { from, to, ramp } => ramp.value(linear_gradient(point, from, to)),
+            Texture::UvChecker {
+                frequency_u,
+                frequency_v,
+                odd,
+                even,
+            } => {
+                let cell = (u * frequency_u).floor() as i32 + (v * frequency_v).floor() as i32;
+                if cell.rem_euclid(2) == 1 {
+                    odd.value(u, v, point)
+                } else {
+                    even.value(u, v, point)
+                }
+            }
+            Texture::UvGrid {
+                frequency_u,
+                frequency_v,
+                line_width,
+                line,
+                fill,
+            } => {
+                let on_line = |t: f32| {
+                    let f = t - t.floor();
+                    f < 0.5 * line_width || f > 1.0 - 0.5 * line_width
+                };
+                if on_line(u * frequency_u) || on_line(v * frequency_v) {
+                    line.value(u, v, point)
+                } else {
+                    fill.value(u, v, point)
+                }
+            }
+            Texture::Mix { a, b, factor } => {
+                let t = factor.value(u, v, point).x.clamp(0.0, 1.0);
+                let (a, b) = (a.value(u, v, point), b.value(u, v, point));
+                a + (b - a) * t
+            }
+            Texture::Multiply { a, b } => {
+                let (a, b) = (a.value(u, v, point), b.value(u, v, point));
+                Point3::new(a.x * b.x, a.y * b.y, a.z * b.z)
+            }
+            Texture::Transformed {
+                texture,
+                space,
+                transform,
+            } => match space {
+                TextureSpace::Object => texture.value(u, v, &transform.apply(point)),
+                TextureSpace::Uv => {
+                    let p = transform.apply(&Point3::new(u, v, 0.0));
+                    texture.value(p.x, p.y, &p)
+                }
+            },
+            Texture::Image { image, wrap } => {
+                let texel = image.sample(u, v, *wrap);
+                Point3::new(texel.x, texel.y, texel.z)
+            }
+            Texture::Alpha { image, wrap } => {
+                let alpha = image.sample(u, v, *wrap).w;
+                Point3::new(alpha, alpha, alpha)
             }
         }
     }
@@ -188,7 +352,229 @@ impl Texture {
 
 pub enum Material {
     Lambertian { texture: Texture },
+    // Rough diffuse surface such as clay or cloth, `sigma` is the standard deviation of the
+    // facet slopes in degrees.
+    OrenNayar { texture: Texture, sigma: f32 },
     Metallic { r: f32, g: f32, b: f32 },
-    Dielectric { refractive_index: f32 },
-    DiffuseLight { texture: Texture },
+    Dielectric {
+        refractive_index: f32,
+        thin_film: Option<ThinFilm>,
+    },
+    // Emits `strength` times the texture color, from the front of the surface only unless
+    // `two_sided` is set.
+    DiffuseLight {
+        texture: Texture,
+        strength: f32,
+        two_sided: bool,
+    },
+    // GGX microfacet conductor, `eta` and `k` are the real and imaginary parts of the
+    // complex index of refraction sampled at red, green and blue wavelengths.
+    Conductor {
+        eta: Vector3<f32>,
+        k: Vector3<f32>,
+        roughness: f32,
+        anisotropy: f32,
+        thin_film: Option<ThinFilm>,
+    },
+    // Glass with a GGX microfacet interface, light travelling inside is absorbed according
+    // to the Beer-Lambert law.
+    Glass {
+        refractive_index: f32,
+        roughness: f32,
+        absorption: Vector3<f32>,
+    },
+    Principled {
+        bsdf: Box<PrincipledBsdf>,
+    },
+    // Clear dielectric whose index of refraction varies with wavelength, it disperses light
+    // in the spectral render mode.
+    DispersiveDielectric {
+        refractive_index: RefractiveIndex,
+    },
+    // Translucent material scattering light below the surface by a random walk through the
+    // closed object, behind a smooth dielectric boundary. Mean free paths are in world units.
+    Subsurface {
+        refractive_index: f32,
+        albedo: Vector3<f32>,
+        mean_free_path: Vector3<f32>,
+        anisotropy: Vector3<f32>,
+    },
+    // Clear dielectric coat over any other material, evaluated by stochastic layering.
+    Coated {
+        coat: Coat,
+        base: Box<Material>,
+    },
+    // Cuts holes into any material, the first channel of `opacity` is the probability that
+    // a hit is kept. Masks are resolved by `World::trace`, integrators only see `material`.
+    Masked {
+        opacity: Texture,
+        material: Box<Material>,
+    },
+    // Shades each hit with `b` with the probability given by the first channel of `factor`,
+    // and with `a` otherwise. Like masks, mixes are resolved by `World::trace`.
+    Mix {
+        a: Box<Material>,
+        b: Box<Material>,
+        factor: Texture,
+    },
+}
+
+impl Material {
+    // Base color of the surface, used by the flat shading render mode.
+    pub fn albedo(&self, u: f32, v: f32, point: &Point3<f32>) -> Vector3<f32> {
+        match self {
+            Material::Lambertian { texture }
+            | Material::OrenNayar { texture, .. }
+            | Material::DiffuseLight { texture, .. } => {
+                let Point3 { x, y, z } = texture.value(u, v, point);
+                vec3(x, y, z)
+            }
+            Material::Metallic { r, g, b } => vec3(*r, *g, *b),
+            Material::Dielectric { .. } | Material::DispersiveDielectric { .. } => {
+                vec3(1.0, 1.0, 1.0)
+            }
+            Material::Conductor {
+                eta,
+                k,
+                thin_film: None,
+                ..
+            } => fresnel_conductor(1.0, eta, k),
+            Material::Conductor {
+                eta,
+                k,
+                thin_film: Some(film),
+                ..
+            } => film.reflectance3(1.0, 1.0, eta, k, &RGB_WAVELENGTHS),
+            Material::Glass { absorption, .. } => beer_lambert(absorption, 1.0),
+            Material::Principled { bsdf } => bsdf.evaluate(u, v, point).base_color,
+            Material::Subsurface { albedo, .. } => *albedo,
+            Material::Coated { base, .. } => base.albedo(u, v, point),
+            Material::Masked { material, .. } => material.albedo(u, v, point),
+            Material::Mix { a, b, factor } => {
+                let t = factor.value(u, v, point).x.clamp(0.0, 1.0);
+                a.albedo(u, v, point).lerp(b.albedo(u, v, point), t)
+            }
+        }
+    }
+
+    // Radiance emitted back along `direction` from the hit, zero for all but lights.
+    pub fn emitted(&self, rec: &ShadeRecord, direction: &Vector3<f32>) -> Vector3<f32> {
+        match self {
+            Material::DiffuseLight {
+                texture,
+                strength,
+                two_sided,
+            } => {
+                if !two_sided && direction.dot(rec.geometric_normal) > 0.0 {
+                    return vec3(0.0, 0.0, 0.0);
+                }
+                let Point3 { x, y, z } = texture.value(rec.u, rec.v, &rec.object_hit_point);
+                vec3(x, y, z) * *strength
+            }
+            _ => vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub const fn oren_nayar(texture: Texture, sigma: f32) -> Self {
+        Material::OrenNayar { texture, sigma }
+    }
+
+    // Isotropic subsurface scattering behind a boundary with the index of refraction of skin.
+    pub fn subsurface(albedo: Vector3<f32>, mean_free_path: Vector3<f32>) -> Self {
+        Material::Subsurface {
+            refractive_index: 1.4,
+            albedo,
+            mean_free_path,
+            anisotropy: vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    // One-sided light with the color of a blackbody at `kelvin`.
+    pub fn blackbody_light(kelvin: f32, strength: f32) -> Self {
+        let color = blackbody(kelvin);
+        Material::DiffuseLight {
+            texture: Texture::Constant {
+                color: Point3::new(color.x, color.y, color.z),
+            },
+            strength,
+            two_sided: false,
+        }
+    }
+
+    pub fn conductor(eta: Vector3<f32>, k: Vector3<f32>, roughness: f32, anisotropy: f32) -> Self {
+        Material::Conductor {
+            eta,
+            k,
+            roughness: roughness.clamp(0.0, 1.0),
+            anisotropy: anisotropy.clamp(0.0, 1.0),
+            thin_film: None,
+        }
+    }
+
+    // Mix of two materials, `factor` is the probability of shading a hit with `b`.
+    pub fn mix(a: Material, b: Material, factor: Texture) -> Self {
+        Material::Mix {
+            a: Box::new(a),
+            b: Box::new(b),
+            factor,
+        }
+    }
+
+    // Randomly picks one of the materials of a mix, other materials are returned as they are.
+    pub fn pick_mixed(&self, u: f32, v: f32, point: &Point3<f32>) -> &Material {
+        match self {
+            Material::Mix { a, b, factor } => {
+                let t = factor.value(u, v, point).x;
+                if t >= 1.0 || (t > 0.0 && random::<f32>() < t) {
+                    b
+                } else {
+                    a
+                }
+            }
+            _ => self,
+        }
+    }
+
+    pub fn coated(self, coat: Coat) -> Self {
+        Material::Coated {
+            coat,
+            base: Box::new(self),
+        }
+    }
+
+    // Coats a dielectric or a conductor with a thin film, other materials are unchanged.
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        if let Material::Dielectric { thin_film, .. } | Material::Conductor { thin_film, .. } =
+            &mut self
+        {
+            *thin_film = Some(film);
+        }
+        self
+    }
+
+    // `color` is the fraction of light that remains after travelling `distance` inside the glass.
+    pub fn glass(refractive_index: f32, roughness: f32, color: Vector3<f32>, distance: f32) -> Self {
+        let absorption = |c: f32| -c.clamp(1e-4, 1.0).ln() / distance.max(1e-4);
+        Material::Glass {
+            refractive_index,
+            roughness: roughness.clamp(0.0, 1.0),
+            absorption: vec3(absorption(color.x), absorption(color.y), absorption(color.z)),
+        }
+    }
+
+    pub fn gold(roughness: f32) -> Self {
+        Material::conductor(vec3(0.143, 0.374, 1.442), vec3(3.983, 2.385, 1.603), roughness, 0.0)
+    }
+
+    pub fn copper(roughness: f32) -> Self {
+        Material::conductor(vec3(0.200, 0.924, 1.102), vec3(3.912, 2.452, 2.142), roughness, 0.0)
+    }
+
+    pub fn silver(roughness: f32) -> Self {
+        Material::conductor(vec3(0.155, 0.117, 0.138), vec3(4.828, 3.122, 2.147), roughness, 0.0)
+    }
+
+    pub fn aluminium(roughness: f32) -> Self {
+        Material::conductor(vec3(1.657, 0.880, 0.521), vec3(9.224, 6.270, 4.837), roughness, 0.0)
+    }
 }
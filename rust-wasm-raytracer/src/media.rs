@@ -0,0 +1,281 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use rand::random;
+use std::f32;
+
+use crate::aabb::Aabb;
+use crate::noise::Perlin;
+use crate::Ray;
+
+#[derive(Clone)]
+pub struct DensityGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    values: Vec<f32>,
+    max_value: f32,
+}
+
+impl DensityGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, values: Vec<f32>) -> Result<Self, String> {
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(format!("invalid grid resolution {}x{}x{}", nx, ny, nz));
+        }
+        if values.len() != nx * ny * nz {
+            return Err(format!(
+                "expected {} density values, got {}",
+                nx * ny * nz,
+                values.len()
+            ));
+        }
+        let max_value = values.iter().cloned().fold(0.0, f32::max);
+        Ok(DensityGrid {
+            nx,
+            ny,
+            nz,
+            values,
+            max_value,
+        })
+    }
+
+    // Raw binary layout: little-endian f32 values, x varying fastest, then y, then z.
+    pub fn from_raw_bytes(nx: usize, ny: usize, nz: usize, bytes: &[u8]) -> Result<Self, String> {
+        if !bytes.len().is_multiple_of(4) {
+            return Err(format!(
+                "raw density data length {} is not a multiple of 4",
+                bytes.len()
+            ));
+        }
+        let values = bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        DensityGrid::new(nx, ny, nz, values)
+    }
+
+    // Text layout: "nx ny nz" followed by nx * ny * nz whitespace separated values.
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut tokens = text.split_whitespace();
+        let mut next_dimension = || -> Result<usize, String> {
+            tokens
+                .next()
+                .ok_or_else(|| String::from("missing grid resolution"))?
+                .parse::<usize>()
+                .map_err(|e| e.to_string())
+        };
+        let (nx, ny, nz) = (next_dimension()?, next_dimension()?, next_dimension()?);
+        let values = tokens
+            .map(|token| token.parse::<f32>().map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        DensityGrid::new(nx, ny, nz, values)
+    }
+
+    #[inline]
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[x + self.nx * (y + self.ny * z)]
+    }
+
+    // Trilinear lookup, `local` is in [0, 1]^3 over the whole grid.
+    pub fn lookup(&self, local: &Point3<f32>) -> f32 {
+        let gx = (local.x * self.nx as f32 - 0.5).clamp(0.0, (self.nx - 1) as f32);
+        let gy = (local.y * self.ny as f32 - 0.5).clamp(0.0, (self.ny - 1) as f32);
+        let gz = (local.z * self.nz as f32 - 0.5).clamp(0.0, (self.nz - 1) as f32);
+
+        let (x0, y0, z0) = (
+            gx.floor() as usize,
+            gy.floor() as usize,
+            gz.floor() as usize,
+        );
+        let (x1, y1, z1) = (
+            (x0 + 1).min(self.nx - 1),
+            (y0 + 1).min(self.ny - 1),
+            (z0 + 1).min(self.nz - 1),
+        );
+        let (u, v, w) = (gx - x0 as f32, gy - y0 as f32, gz - z0 as f32);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), u);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), u);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), u);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), u);
+        lerp(lerp(c00, c10, v), lerp(c01, c11, v), w)
+    }
+}
+
+pub enum DensityField {
+    Grid(DensityGrid),
+    Noise { perlin: Perlin, octaves: u8 },
+}
+
+impl DensityField {
+    pub fn value(&self, local: &Point3<f32>) -> f32 {
+        match self {
+            DensityField::Grid(grid) => grid.lookup(local),
+            DensityField::Noise { perlin, octaves } => {
+                let mut acc = 0.0;
+                let mut temp_p = *local;
+                let mut weight = 1.0;
+                for _i in 0..*octaves {
+                    acc += weight * perlin.generate_noise(&temp_p);
+                    weight *= 0.5;
+                    temp_p *= 2.0;
+                }
+                (0.5 * (1.0 + acc)).clamp(0.0, 1.0)
+            }
+        }
+    }
+
+    pub fn max_value(&self) -> f32 {
+        match self {
+            DensityField::Grid(grid) => grid.max_value,
+            DensityField::Noise { .. } => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct HenyeyGreenstein {
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f32) -> Self {
+        HenyeyGreenstein {
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    // Density of scattering by an angle with cosine `cos_theta`, per unit solid angle.
+    pub fn evaluate(&self, cos_theta: f32) -> f32 {
+        let denominator = 1.0 + self.g * self.g - 2.0 * self.g * cos_theta;
+        (1.0 - self.g * self.g) / (4.0 * f32::consts::PI * denominator * denominator.sqrt())
+    }
+
+    // Samples a new direction given the incoming ray direction. The phase function is
+    // importance sampled exactly, so no additional weight is needed.
+    pub fn sample(&self, incoming: &Vector3<f32>) -> Vector3<f32> {
+        let (u1, u2) = (random::<f32>(), random::<f32>());
+        let cos_theta = if self.g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let square = (1.0 - self.g * self.g) / (1.0 + self.g - 2.0 * self.g * u1);
+            (1.0 + self.g * self.g - square * square) / (2.0 * self.g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * f32::consts::PI * u2;
+
+        let w = incoming.normalize();
+        let helper = if w.x.abs() > 0.9 {
+            vec3(0.0, 1.0, 0.0)
+        } else {
+            vec3(1.0, 0.0, 0.0)
+        };
+        let u = helper.cross(w).normalize();
+        let v = w.cross(u);
+
+        u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + w * cos_theta
+    }
+}
+
+pub struct MediumInteraction<'a> {
+    pub point: Point3<f32>,
+    pub intersect_parameter: f32,
+    pub medium: &'a HeterogeneousMedium,
+}
+
+pub struct HeterogeneousMedium {
+    bounds: Aabb,
+    density: DensityField,
+    sigma_t: f32,
+    pub albedo: Vector3<f32>,
+    pub phase: HenyeyGreenstein,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(
+        bounds: Aabb,
+        density: DensityField,
+        sigma_t: f32,
+        albedo: Vector3<f32>,
+        g: f32,
+    ) -> Self {
+        HeterogeneousMedium {
+            bounds,
+            density,
+            sigma_t,
+            albedo,
+            phase: HenyeyGreenstein::new(g),
+        }
+    }
+
+    #[inline]
+    fn local_point(&self, point: &Point3<f32>) -> Point3<f32> {
+        let extent = self.bounds.max - self.bounds.min;
+        let offset = point - self.bounds.min;
+        Point3::new(
+            offset.x / extent.x,
+            offset.y / extent.y,
+            offset.z / extent.z,
+        )
+    }
+
+    #[inline]
+    fn majorant(&self) -> f32 {
+        self.sigma_t * self.density.max_value()
+    }
+
+    // Delta tracking: returns the first real collision along the ray before `t_max`.
+    pub fn sample_interaction(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<MediumInteraction<'_>> {
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return None;
+        }
+        let (t_enter, t_exit) = self.bounds.hit(ray, t_min, t_max)?;
+        let direction_length = ray.direction.magnitude();
+        let mut t = t_enter;
+
+        loop {
+            t -= (1.0 - random::<f32>()).ln() / (majorant * direction_length);
+            if t >= t_exit {
+                return None;
+            }
+            let point = ray.point_at_parameter(t);
+            let density = self.density.value(&self.local_point(&point));
+            if random::<f32>() < density * self.sigma_t / majorant {
+                return Some(MediumInteraction {
+                    point,
+                    intersect_parameter: t,
+                    medium: self,
+                });
+            }
+        }
+    }
+
+    // Ratio tracking estimate of the transmittance between `t_min` and `t_max`.
+    pub fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
+        let majorant = self.majorant();
+        let (t_enter, t_exit) = match self.bounds.hit(ray, t_min, t_max) {
+            Some(interval) if majorant > 0.0 => interval,
+            _ => return 1.0,
+        };
+        let direction_length = ray.direction.magnitude();
+        let mut t = t_enter;
+        let mut transmittance = 1.0;
+
+        loop {
+            t -= (1.0 - random::<f32>()).ln() / (majorant * direction_length);
+            if t >= t_exit {
+                return transmittance;
+            }
+            let density = self
+                .density
+                .value(&self.local_point(&ray.point_at_parameter(t)));
+            transmittance *= 1.0 - density * self.sigma_t / majorant;
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+use rand::random;
+use std::f32;
+
+// Orthonormal shading frame, local coordinates have the normal along +z.
+pub struct Frame {
+    tangent: Vector3<f32>,
+    bitangent: Vector3<f32>,
+    normal: Vector3<f32>,
+}
+
+impl Frame {
+    // Frame with its x axis along `tangent` projected onto the surface, so that anisotropic
+    // lobes follow the surface parametrization.
+    pub fn new(normal: &Vector3<f32>, tangent: &Vector3<f32>) -> Self {
+        let normal = normal.normalize();
+        let projected = tangent - normal * normal.dot(*tangent);
+        if projected.magnitude2() < 1e-12 {
+            return Frame::from_normal(&normal);
+        }
+        let tangent = projected.normalize();
+        Frame {
+            tangent,
+            bitangent: normal.cross(tangent),
+            normal,
+        }
+    }
+
+    pub fn from_normal(normal: &Vector3<f32>) -> Self {
+        let normal = normal.normalize();
+        let helper = if normal.x.abs() > 0.9 {
+            vec3(0.0, 1.0, 0.0)
+        } else {
+            vec3(1.0, 0.0, 0.0)
+        };
+        let tangent = helper.cross(normal).normalize();
+        Frame {
+            tangent,
+            bitangent: normal.cross(tangent),
+            normal,
+        }
+    }
+
+    #[inline]
+    pub fn to_local(&self, v: &Vector3<f32>) -> Vector3<f32> {
+        vec3(
+            v.dot(self.tangent),
+            v.dot(self.bitangent),
+            v.dot(self.normal),
+        )
+    }
+
+    #[inline]
+    pub fn to_world(&self, v: &Vector3<f32>) -> Vector3<f32> {
+        self.tangent * v.x + self.bitangent * v.y + self.normal * v.z
+    }
+}
+
+// Local direction distributed with density cos(theta) / pi over the upper hemisphere, from a
+// uniformly sampled point of the unit disc projected up.
+pub fn sample_cosine_hemisphere() -> Vector3<f32> {
+    let (u1, u2) = (random::<f32>(), random::<f32>());
+    let r = u1.sqrt();
+    let phi = 2.0 * f32::consts::PI * u2;
+    vec3(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+// Oren-Nayar model of a rough diffuse surface made of V-shaped Lambertian facets, whose slopes
+// have a standard deviation of `sigma` degrees. A sigma of zero is Lambertian.
+pub struct OrenNayarBrdf {
+    a: f32,
+    b: f32,
+}
+
+impl OrenNayarBrdf {
+    pub fn new(sigma: f32) -> Self {
+        let sigma2 = sigma.to_radians().powi(2);
+        OrenNayarBrdf {
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    // Reflectance relative to a Lambertian surface of the same albedo, for local directions in
+    // the upper hemisphere. Multiplied with albedo / pi it gives the BRDF.
+    pub fn evaluate(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+        let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+        if sin_o < 1e-4 || sin_i < 1e-4 {
+            return self.a;
+        }
+
+        // Cosine of the azimuthal angle between the directions.
+        let cos_phi = ((wo.x * wi.x + wo.y * wi.y) / (sin_o * sin_i)).max(0.0);
+        let (sin_alpha, tan_beta) = if wi.z < wo.z {
+            (sin_i, sin_o / wo.z)
+        } else {
+            (sin_o, sin_i / wi.z)
+        };
+        self.a + self.b * cos_phi * sin_alpha * tan_beta
+    }
+}
+
+#[inline]
+pub fn reflect_local(wo: &Vector3<f32>, h: &Vector3<f32>) -> Vector3<f32> {
+    h * (2.0 * wo.dot(*h)) - wo
+}
+
+// Exact Fresnel reflectance of a conductor with complex index of refraction eta + i k,
+// evaluated per color channel.
+pub fn fresnel_conductor(cos_theta: f32, eta: &Vector3<f32>, k: &Vector3<f32>) -> Vector3<f32> {
+    let channel = |eta: f32, k: f32| {
+        let cos2 = cos_theta.clamp(0.0, 1.0) * cos_theta.clamp(0.0, 1.0);
+        let sin2 = 1.0 - cos2;
+        let (eta2, k2) = (eta * eta, k * k);
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_theta.clamp(0.0, 1.0) * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    vec3(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
+
+// Anisotropic GGX (Trowbridge-Reitz) distribution with Smith masking-shadowing. All
+// directions are in the local shading frame.
+pub struct Ggx {
+    alpha_x: f32,
+    alpha_y: f32,
+}
+
+impl Ggx {
+    // Perceptual roughness is squared, anisotropy in [0, 1) stretches the lobe along the tangent.
+    pub fn from_roughness(roughness: f32, anisotropy: f32) -> Self {
+        let alpha = (roughness * roughness).max(1e-4);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        Ggx {
+            alpha_x: (alpha / aspect).max(1e-4),
+            alpha_y: (alpha * aspect).max(1e-4),
+        }
+    }
+
+    pub fn distribution(&self, h: &Vector3<f32>) -> f32 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let e = (h.x / self.alpha_x).powi(2) + (h.y / self.alpha_y).powi(2) + h.z * h.z;
+        1.0 / (f32::consts::PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    fn lambda(&self, w: &Vector3<f32>) -> f32 {
+        if w.z == 0.0 {
+            return f32::MAX;
+        }
+        let tan2 = ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / (w.z * w.z);
+        0.5 * (-1.0 + (1.0 + tan2).sqrt())
+    }
+
+    #[inline]
+    pub fn masking(&self, w: &Vector3<f32>) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    #[inline]
+    pub fn masking_shadowing(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of reflected directions produced by `sample_visible_normal`.
+    pub fn reflection_pdf(&self, wo: &Vector3<f32>, h: &Vector3<f32>) -> f32 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.masking(wo) * wo.dot(*h).max(0.0) * self.distribution(h) / wo.z / (4.0 * wo.dot(*h))
+    }
+
+    // Samples a microfacet normal from the distribution of normals visible from `wo` (Heitz 2018).
+    pub fn sample_visible_normal(&self, wo: &Vector3<f32>) -> Vector3<f32> {
+        let (u1, u2) = (random::<f32>(), random::<f32>());
+        let vh = vec3(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+
+        let length2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length2 > 0.0 {
+            vec3(-vh.y, vh.x, 0.0) / length2.sqrt()
+        } else {
+            vec3(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * f32::consts::PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        vec3(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+    }
+}
+
+// Rough conductor BRDF for local directions, without the cosine term. `fresnel` gives the
+// reflectance for the cosine between the view direction and the microfacet normal.
+pub fn conductor_brdf<F>(
+    ggx: &Ggx,
+    fresnel: &F,
+    wo: &Vector3<f32>,
+    wi: &Vector3<f32>,
+) -> Vector3<f32>
+where
+    F: Fn(f32) -> Vector3<f32>,
+{
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return vec3(0.0, 0.0, 0.0);
+    }
+    let h = (wo + wi).normalize();
+    fresnel(wo.dot(h))
+        * (ggx.distribution(&h) * ggx.masking_shadowing(wo, wi) / (4.0 * wo.z * wi.z))
+}
+
+// Samples a reflected local direction off a rough conductor, the returned weight is the
+// BRDF times the cosine divided by the pdf of the direction.
+pub fn sample_conductor<F>(
+    ggx: &Ggx,
+    fresnel: &F,
+    wo: &Vector3<f32>,
+) -> Option<(Vector3<f32>, Vector3<f32>)>
+where
+    F: Fn(f32) -> Vector3<f32>,
+{
+    if wo.z <= 0.0 {
+        return None;
+    }
+    let h = ggx.sample_visible_normal(wo);
+    let wi = reflect_local(wo, &h);
+    if wi.z <= 0.0 {
+        return None;
+    }
+    let weight = fresnel(wo.dot(h)) * (ggx.masking_shadowing(wo, &wi) / ggx.masking(wo));
+    Some((wi, weight))
+}
+
+// Exact Fresnel reflectance of a dielectric interface, `eta` is the ratio of the index of
+// refraction on the transmitted side to the one on the incident side.
+pub fn fresnel_dielectric(cos_theta: f32, eta: f32) -> f32 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+#[inline]
+pub(crate) fn refract_local(wo: &Vector3<f32>, h: &Vector3<f32>, eta: f32) -> Option<Vector3<f32>> {
+    let cos_i = wo.dot(*h);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*wo / eta + h * (cos_i / eta - cos_t))
+}
+
+// Samples a rough dielectric interface, choosing reflection or transmission through the
+// sampled microfacet by its Fresnel reflectance. Returns the local direction, its weight
+// and whether the direction was transmitted.
+pub fn sample_dielectric(
+    ggx: &Ggx,
+    eta: f32,
+    wo: &Vector3<f32>,
+) -> Option<(Vector3<f32>, f32, bool)> {
+    if wo.z <= 0.0 {
+        return None;
+    }
+    let h = ggx.sample_visible_normal(wo);
+    let reflectance = fresnel_dielectric(wo.dot(h), eta);
+
+    let (wi, transmitted) = match refract_local(wo, &h, eta) {
+        Some(refracted) if random::<f32>() >= reflectance => (refracted, true),
+        _ => (reflect_local(wo, &h), false),
+    };
+    if (wi.z < 0.0) != transmitted || wi.z == 0.0 {
+        return None;
+    }
+    Some((
+        wi,
+        ggx.masking_shadowing(wo, &wi) / ggx.masking(wo),
+        transmitted,
+    ))
+}
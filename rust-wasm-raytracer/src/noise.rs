@@ -0,0 +1,200 @@
+use arr_macro::arr;
+use cgmath::prelude::*;
+use cgmath::{vec2, vec3, vec4, Point3, Vector2, Vector3, Vector4};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::f32;
+
+// Gradient noise with its own permutation and gradient tables, so that noisy textures, media and
+// terrains can be seeded independently. Every lookup multiplies the input by `scale`.
+pub struct Perlin {
+    pub scale: f32,
+    permutation: Box<[u8; 256]>,
+    gradients2: Box<[Vector2<f32>; 256]>,
+    gradients3: Box<[Vector3<f32>; 256]>,
+    gradients4: Box<[Vector4<f32>; 256]>,
+}
+
+// Lattice and dimension that `Perlin::sample` reads, the two dimensional bases take the texture
+// coordinates and the others the hit point.
+#[derive(Clone, Copy)]
+pub enum NoiseBasis {
+    Perlin,
+    PerlinUv,
+    // A slice of four dimensional noise at a fixed `w`.
+    Perlin4d { w: f32 },
+    Simplex,
+    SimplexUv,
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+// Skew and unskew factors mapping between simplex and cubic lattices.
+const SKEW_2D: f32 = 0.366_025_42;
+const UNSKEW_2D: f32 = 0.211_324_87;
+const SKEW_3D: f32 = 1.0 / 3.0;
+const UNSKEW_3D: f32 = 1.0 / 6.0;
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut signed = move || -1.0 + 2.0 * rng.gen::<f32>();
+
+        let mut i = 0_u16;
+        let mut permutation = arr![{ i += 1; (i - 1) as u8 }; 256];
+        permutation.shuffle(&mut StdRng::seed_from_u64(seed.wrapping_add(1)));
+
+        Perlin {
+            scale: 1.0,
+            permutation: Box::new(permutation),
+            gradients2: Box::new(arr![vec2(signed(), signed()).normalize(); 256]),
+            gradients3: Box::new(arr![vec3(signed(), signed(), signed()).normalize(); 256]),
+            gradients4: Box::new(
+                arr![vec4(signed(), signed(), signed(), signed()).normalize(); 256],
+            ),
+        }
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    fn hash<const N: usize>(&self, lattice: [i32; N]) -> usize {
+        lattice.iter().fold(0, |h, &c| {
+            self.permutation[(h + (c & 255) as usize) & 255] as usize
+        })
+    }
+
+    // Sums the gradient ramps of the 2^N corners of the lattice cell around `point`, weighted by
+    // a smooth Hermite fade of the position inside the cell.
+    fn gradient_noise<const N: usize>(
+        &self,
+        point: [f32; N],
+        ramp: impl Fn(usize, [f32; N]) -> f32,
+    ) -> f32 {
+        let point = point.map(|c| c * self.scale);
+        let cell = point.map(|c| c.floor());
+        let local: [f32; N] = std::array::from_fn(|d| point[d] - cell[d]);
+        let fade = local.map(|t| t * t * (3.0 - 2.0 * t));
+
+        let mut acc = 0.0;
+        for corner in 0..1 << N {
+            let mut lattice = [0; N];
+            let mut offset = [0.0; N];
+            let mut weight = 1.0;
+            for d in 0..N {
+                let bit = (corner >> d) & 1;
+                lattice[d] = cell[d] as i32 + bit;
+                offset[d] = local[d] - bit as f32;
+                weight *= if bit == 1 { fade[d] } else { 1.0 - fade[d] };
+            }
+            acc += weight * ramp(self.hash(lattice), offset);
+        }
+        acc
+    }
+
+    pub fn generate_noise_2d(&self, x: f32, y: f32) -> f32 {
+        self.gradient_noise([x, y], |h, [dx, dy]| self.gradients2[h].dot(vec2(dx, dy)))
+    }
+
+    pub fn generate_noise(&self, point: &Point3<f32>) -> f32 {
+        self.gradient_noise([point.x, point.y, point.z], |h, [dx, dy, dz]| {
+            self.gradients3[h].dot(vec3(dx, dy, dz))
+        })
+    }
+
+    // Three dimensional noise evolving along `w`, for example time in animated textures.
+    pub fn generate_noise_4d(&self, point: &Point3<f32>, w: f32) -> f32 {
+        self.gradient_noise([point.x, point.y, point.z, w], |h, [dx, dy, dz, dw]| {
+            self.gradients4[h].dot(vec4(dx, dy, dz, dw))
+        })
+    }
+
+    pub fn simplex_2d(&self, x: f32, y: f32) -> f32 {
+        let (x, y) = (x * self.scale, y * self.scale);
+        let skew = (x + y) * SKEW_2D;
+        let (i, j) = ((x + skew).floor(), (y + skew).floor());
+        let unskew = (i + j) * UNSKEW_2D;
+        let first = vec2(x - (i - unskew), y - (j - unskew));
+
+        // The middle corner of the triangle containing the point.
+        let (i1, j1) = if first.x > first.y { (1, 0) } else { (0, 1) };
+        let corners = [
+            ((0, 0), first),
+            (
+                (i1, j1),
+                first - vec2(i1 as f32, j1 as f32) + vec2(UNSKEW_2D, UNSKEW_2D),
+            ),
+            (
+                (1, 1),
+                first - vec2(1.0, 1.0) + vec2(2.0 * UNSKEW_2D, 2.0 * UNSKEW_2D),
+            ),
+        ];
+
+        let (i, j) = (i as i32, j as i32);
+        let acc: f32 = corners
+            .iter()
+            .map(|((di, dj), offset)| {
+                let t = 0.5 - offset.magnitude2();
+                if t <= 0.0 {
+                    return 0.0;
+                }
+                let gradient = self.gradients2[self.hash([i + di, j + dj])];
+                t * t * t * t * gradient.dot(*offset)
+            })
+            .sum();
+        // Brings the extremes of the sum to about [-1, 1].
+        acc * 99.0
+    }
+
+    pub fn simplex_3d(&self, point: &Point3<f32>) -> f32 {
+        let p = point.to_vec() * self.scale;
+        let skew = (p.x + p.y + p.z) * SKEW_3D;
+        let cell = vec3(
+            (p.x + skew).floor(),
+            (p.y + skew).floor(),
+            (p.z + skew).floor(),
+        );
+        let unskew = (cell.x + cell.y + cell.z) * UNSKEW_3D;
+        let first = p - cell + vec3(unskew, unskew, unskew);
+
+        // Walks from the first to the last corner of the tetrahedron containing the point, one
+        // axis at a time in order of decreasing offset.
+        let mut axes = [(first.x, 0), (first.y, 1), (first.z, 2)];
+        axes.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut lattice = [cell.x as i32, cell.y as i32, cell.z as i32];
+        let mut step = Vector3::zero();
+        let mut acc = 0.0;
+        for corner in 0..4 {
+            if corner > 0 {
+                let axis = axes[corner - 1].1;
+                lattice[axis] += 1;
+                step[axis] += 1.0;
+            }
+            let offset = first - step + vec3(1.0, 1.0, 1.0) * (corner as f32 * UNSKEW_3D);
+            let t = 0.6 - offset.magnitude2();
+            if t > 0.0 {
+                acc += t * t * t * t * self.gradients3[self.hash(lattice)].dot(offset);
+            }
+        }
+        // Brings the extremes of the sum to about [-1, 1].
+        acc * 40.0
+    }
+
+    pub fn sample(&self, basis: NoiseBasis, u: f32, v: f32, point: &Point3<f32>) -> f32 {
+        match basis {
+            NoiseBasis::Perlin => self.generate_noise(point),
+            NoiseBasis::PerlinUv => self.generate_noise_2d(u, v),
+            NoiseBasis::Perlin4d { w } => self.generate_noise_4d(point, w),
+            NoiseBasis::Simplex => self.simplex_3d(point),
+            NoiseBasis::SimplexUv => self.simplex_2d(u, v),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use rand::random;
+use std::f32;
+
+use crate::materials::Texture;
+use crate::microfacet::{reflect_local, sample_cosine_hemisphere, sample_dielectric, Ggx};
+
+// Roughness of the clear coat layer, it is fixed to a glossy finish as in the Disney model.
+const CLEARCOAT_ROUGHNESS: f32 = 0.3;
+
+// Disney style principled BSDF. Scalar parameters are read from the first channel of their
+// texture, so they can vary over the surface like the base color. They are clamped to [0, 1],
+// except for the index of refraction which is kept at or above one.
+pub struct PrincipledBsdf {
+    pub base_color: Texture,
+    pub metallic: Texture,
+    pub roughness: Texture,
+    pub specular: Texture,
+    pub specular_tint: Texture,
+    pub sheen: Texture,
+    pub clearcoat: Texture,
+    pub transmission: Texture,
+    pub refractive_index: Texture,
+}
+
+impl PrincipledBsdf {
+    // A rough plastic like dielectric, override the other parameters with struct update syntax.
+    pub fn new(base_color: Texture) -> Self {
+        PrincipledBsdf {
+            base_color,
+            metallic: Texture::scalar(0.0),
+            roughness: Texture::scalar(0.5),
+            specular: Texture::scalar(0.5),
+            specular_tint: Texture::scalar(0.0),
+            sheen: Texture::scalar(0.0),
+            clearcoat: Texture::scalar(0.0),
+            transmission: Texture::scalar(0.0),
+            refractive_index: Texture::scalar(1.5),
+        }
+    }
+
+    pub fn evaluate(&self, u: f32, v: f32, point: &Point3<f32>) -> PrincipledLobes {
+        let scalar = |texture: &Texture| texture.value(u, v, point).x.clamp(0.0, 1.0);
+        let Point3 { x, y, z } = self.base_color.value(u, v, point);
+        let roughness = scalar(&self.roughness);
+
+        PrincipledLobes {
+            base_color: vec3(x, y, z),
+            metallic: scalar(&self.metallic),
+            specular: scalar(&self.specular),
+            specular_tint: scalar(&self.specular_tint),
+            sheen: scalar(&self.sheen),
+            clearcoat: scalar(&self.clearcoat),
+            transmission: scalar(&self.transmission),
+            refractive_index: self.refractive_index.value(u, v, point).x.max(1.0),
+            roughness,
+            specular_ggx: Ggx::from_roughness(roughness, 0.0),
+            clearcoat_ggx: Ggx::from_roughness(CLEARCOAT_ROUGHNESS, 0.0),
+        }
+    }
+}
+
+#[inline]
+fn luminance(color: &Vector3<f32>) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+#[inline]
+fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+// The principled BSDF with its parameters resolved at a single surface point. Directions
+// are in the local shading frame with the normal facing the incoming ray.
+pub struct PrincipledLobes {
+    pub base_color: Vector3<f32>,
+    metallic: f32,
+    roughness: f32,
+    specular: f32,
+    specular_tint: f32,
+    sheen: f32,
+    clearcoat: f32,
+    transmission: f32,
+    refractive_index: f32,
+    specular_ggx: Ggx,
+    clearcoat_ggx: Ggx,
+}
+
+impl PrincipledLobes {
+    #[inline]
+    fn opaque_weight(&self) -> f32 {
+        1.0 - (1.0 - self.metallic) * self.transmission
+    }
+
+    fn tint(&self) -> Vector3<f32> {
+        let luminance = luminance(&self.base_color);
+        if luminance > 0.0 {
+            self.base_color / luminance
+        } else {
+            vec3(1.0, 1.0, 1.0)
+        }
+    }
+
+    fn specular_f0(&self) -> Vector3<f32> {
+        let white = vec3(1.0, 1.0, 1.0);
+        let dielectric = white.lerp(self.tint(), self.specular_tint) * (0.08 * self.specular);
+        dielectric.lerp(self.base_color, self.metallic)
+    }
+
+    // Reflectance of the specular and clear coat layers for a mirror reflection with the
+    // given cosine, used where the lobes can't be sampled.
+    pub fn specular_reflectance(&self, cosine: f32) -> Vector3<f32> {
+        let f0 = self.specular_f0();
+        let weight = schlick_weight(cosine);
+        let specular = (f0 + (vec3(1.0, 1.0, 1.0) - f0) * weight) * self.opaque_weight();
+        let clearcoat = 0.25 * self.clearcoat * (0.04 + 0.96 * weight);
+        specular + vec3(clearcoat, clearcoat, clearcoat)
+    }
+
+    // Fraction of the light refracted into the surface at normal incidence, tinted by the
+    // base color.
+    pub fn transmittance(&self) -> Vector3<f32> {
+        self.base_color * ((1.0 - self.metallic) * self.transmission)
+    }
+
+    // Probabilities of sampling the diffuse, specular, clear coat and transmission lobes.
+    fn lobe_probabilities(&self, wo: &Vector3<f32>) -> [f32; 4] {
+        let opaque = self.opaque_weight();
+        let diffuse = opaque * (1.0 - self.metallic) * luminance(&self.base_color).max(0.05);
+        let specular = opaque * luminance(&self.specular_reflectance(wo.z)).max(0.05);
+        let clearcoat = 0.25 * self.clearcoat * (0.04 + 0.96 * schlick_weight(wo.z));
+        let transmission = (1.0 - self.metallic) * self.transmission;
+
+        let total = diffuse + specular + clearcoat + transmission;
+        [
+            diffuse / total,
+            specular / total,
+            clearcoat / total,
+            transmission / total,
+        ]
+    }
+
+    // Sum of the diffuse, sheen, specular and clear coat lobes, without the cosine term. The
+    // transmission lobe is only reachable through `sample`.
+    pub fn reflection(&self, wo: &Vector3<f32>, wi: &Vector3<f32>) -> Vector3<f32> {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+        let h = (wo + wi).normalize();
+        let cos_d = wi.dot(h);
+        let opaque = self.opaque_weight();
+
+        // Burley diffuse with retro-reflection at grazing angles for rough surfaces.
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let diffuse_fresnel = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+        let diffuse = self.base_color * (diffuse_fresnel / f32::consts::PI);
+        let sheen_color = vec3(1.0, 1.0, 1.0).lerp(self.tint(), 0.5);
+        let sheen = sheen_color * (self.sheen * schlick_weight(cos_d));
+        let diffuse_weight = opaque * (1.0 - self.metallic);
+
+        let f0 = self.specular_f0();
+        let fresnel = f0 + (vec3(1.0, 1.0, 1.0) - f0) * schlick_weight(cos_d);
+        let specular = fresnel
+            * (opaque
+                * self.specular_ggx.distribution(&h)
+                * self.specular_ggx.masking_shadowing(wo, wi)
+                / (4.0 * wo.z * wi.z));
+
+        let clearcoat = 0.25
+            * self.clearcoat
+            * (0.04 + 0.96 * schlick_weight(cos_d))
+            * self.clearcoat_ggx.distribution(&h)
+            * self.clearcoat_ggx.masking_shadowing(wo, wi)
+            / (4.0 * wo.z * wi.z);
+
+        (diffuse + sheen) * diffuse_weight + specular + vec3(clearcoat, clearcoat, clearcoat)
+    }
+
+    // Samples a local direction by picking one lobe. Reflected directions are weighted with
+    // the combined density of all reflection lobes, transmission is sampled on its own as a
+    // rough dielectric interface. Returns the direction, its weight and whether it entered
+    // or left the surface.
+    pub fn sample(
+        &self,
+        wo: &Vector3<f32>,
+        entering: bool,
+    ) -> Option<(Vector3<f32>, Vector3<f32>, bool)> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let [p_diffuse, p_specular, p_clearcoat, p_transmission] = self.lobe_probabilities(wo);
+        let choice = random::<f32>();
+
+        if choice < p_transmission {
+            let eta = if entering {
+                self.refractive_index
+            } else {
+                1.0 / self.refractive_index
+            };
+            let (wi, weight, transmitted) = sample_dielectric(&self.specular_ggx, eta, wo)?;
+            let tint = if transmitted {
+                self.base_color
+            } else {
+                vec3(1.0, 1.0, 1.0)
+            };
+            let lobe_weight = (1.0 - self.metallic) * self.transmission;
+            return Some((
+                wi,
+                tint * (weight * lobe_weight / p_transmission),
+                transmitted,
+            ));
+        }
+
+        let choice = choice - p_transmission;
+        let wi = if choice < p_diffuse {
+            sample_cosine_hemisphere()
+        } else if choice < p_diffuse + p_specular {
+            reflect_local(wo, &self.specular_ggx.sample_visible_normal(wo))
+        } else {
+            reflect_local(wo, &self.clearcoat_ggx.sample_visible_normal(wo))
+        };
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        let h = (wo + wi).normalize();
+        let pdf = p_diffuse * wi.z / f32::consts::PI
+            + p_specular * self.specular_ggx.reflection_pdf(wo, &h)
+            + p_clearcoat * self.clearcoat_ggx.reflection_pdf(wo, &h);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some((wi, self.reflection(wo, &wi) * (wi.z / pdf), false))
+    }
+}
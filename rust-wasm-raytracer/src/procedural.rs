@@ -0,0 +1,189 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use std::f32;
+
+use crate::noise::Perlin;
+
+// Fractal sum of noise octaves, each `lacunarity` times finer and `gain` times weaker than the
+// previous one. The base frequency is the scale of the noise.
+pub struct Fbm {
+    pub perlin: Perlin,
+    pub octaves: u8,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+impl Fbm {
+    pub const fn new(perlin: Perlin, octaves: u8, lacunarity: f32, gain: f32) -> Self {
+        Fbm {
+            perlin,
+            octaves,
+            lacunarity,
+            gain,
+        }
+    }
+
+    fn sum(&self, point: &Point3<f32>, signal: impl Fn(f32) -> f32) -> f32 {
+        let mut acc = 0.0;
+        let mut total_weight = 0.0;
+        let mut weight = 1.0;
+        let mut p = *point;
+        for _ in 0..self.octaves {
+            acc += weight * signal(self.perlin.generate_noise(&p));
+            total_weight += weight;
+            weight *= self.gain;
+            p *= self.lacunarity;
+        }
+
+        if total_weight > 0.0 {
+            acc / total_weight
+        } else {
+            0.0
+        }
+    }
+
+    // Signed noise in about [-1, 1].
+    pub fn noise(&self, point: &Point3<f32>) -> f32 {
+        self.sum(point, |n| n)
+    }
+
+    // Sum of absolute octaves in [0, 1], with creases where the noise changes sign.
+    pub fn turbulence(&self, point: &Point3<f32>) -> f32 {
+        self.sum(point, f32::abs)
+    }
+}
+
+impl Default for Fbm {
+    fn default() -> Self {
+        Fbm::new(Perlin::default(), 7, 2.0, 0.5)
+    }
+}
+
+// Piecewise linear map from [0, 1] to colors, through stops sorted by position.
+pub struct ColorRamp {
+    stops: Vec<(f32, Point3<f32>)>,
+}
+
+impl ColorRamp {
+    pub fn new(mut stops: Vec<(f32, Point3<f32>)>) -> Result<Self, String> {
+        if stops.is_empty() {
+            return Err("a color ramp needs at least one stop".to_string());
+        }
+        if stops.iter().any(|(position, _)| !position.is_finite()) {
+            return Err("color ramp positions must be finite".to_string());
+        }
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Ok(ColorRamp { stops })
+    }
+
+    pub fn between(from: Point3<f32>, to: Point3<f32>) -> Self {
+        ColorRamp {
+            stops: vec![(0.0, from), (1.0, to)],
+        }
+    }
+
+    pub fn grayscale() -> Self {
+        ColorRamp::between(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn value(&self, t: f32) -> Point3<f32> {
+        let (first, last) = (self.stops[0], self.stops[self.stops.len() - 1]);
+        if t <= first.0 {
+            return first.1;
+        }
+        if t >= last.0 {
+            return last.1;
+        }
+
+        let upper = self
+            .stops
+            .iter()
+            .position(|(position, _)| *position > t)
+            .unwrap();
+        let (p0, c0) = self.stops[upper - 1];
+        let (p1, c1) = self.stops[upper];
+        let s = (t - p0) / (p1 - p0);
+        c0 + (c1 - c0) * s
+    }
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^ (x >> 16)
+}
+
+fn hash_cell(i: i32, j: i32, k: i32, seed: u32) -> u32 {
+    hash(i as u32 ^ hash(j as u32 ^ hash(k as u32 ^ hash(seed))))
+}
+
+fn unit_float(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1 << 24) as f32
+}
+
+// Distances to the nearest and second nearest feature points of a cellular pattern, one feature
+// point per unit cell moved away from the cell center by up to `jitter`.
+pub fn worley(point: &Point3<f32>, jitter: f32, seed: u32) -> (f32, f32) {
+    let cell = vec3(point.x.floor(), point.y.floor(), point.z.floor());
+    let mut nearest = (f32::MAX, f32::MAX);
+
+    for di in -1..=1 {
+        for dj in -1..=1 {
+            for dk in -1..=1 {
+                let neighbour = cell + vec3(di as f32, dj as f32, dk as f32);
+                let h = hash_cell(
+                    neighbour.x as i32,
+                    neighbour.y as i32,
+                    neighbour.z as i32,
+                    seed,
+                );
+                let offset = vec3(
+                    unit_float(h),
+                    unit_float(hash(h)),
+                    unit_float(hash(h ^ 0x9e37_79b9)),
+                );
+                let feature = Point3::from_vec(neighbour)
+                    + (vec3(0.5, 0.5, 0.5) + (offset - vec3(0.5, 0.5, 0.5)) * jitter);
+
+                let distance = feature.distance(*point);
+                if distance < nearest.0 {
+                    nearest = (distance, nearest.0);
+                } else if distance < nearest.1 {
+                    nearest.1 = distance;
+                }
+            }
+        }
+    }
+
+    nearest
+}
+
+// Which quantity of a cellular pattern is mapped through the color ramp.
+#[derive(Clone, Copy)]
+pub enum CellularFeature {
+    Nearest,
+    SecondNearest,
+    Edge,
+}
+
+impl CellularFeature {
+    pub fn select(self, (f1, f2): (f32, f32)) -> f32 {
+        match self {
+            CellularFeature::Nearest => f1,
+            CellularFeature::SecondNearest => f2,
+            CellularFeature::Edge => f2 - f1,
+        }
+    }
+}
+
+// Position of `point` along the axis from `from` to `to`, clamped to [0, 1].
+pub fn linear_gradient(point: &Point3<f32>, from: &Point3<f32>, to: &Point3<f32>) -> f32 {
+    let axis: Vector3<f32> = to - from;
+    let length2 = axis.magnitude2();
+    if length2 == 0.0 {
+        return 0.0;
+    }
+    ((point - from).dot(axis) / length2).clamp(0.0, 1.0)
+}
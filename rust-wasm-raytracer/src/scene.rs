@@ -1,16 +1,223 @@
 use cascade::cascade;
 use cgmath::prelude::*;
-use cgmath::{vec3, Point3, Vector3};
+use cgmath::{vec3, vec4, Point3, Vector3};
 use std::{f32, u16, usize};
 use rand::random;
+use wasm_bindgen::prelude::*;
 
 use crate::{
+    aabb::Aabb,
     camera::Camera,
-    geometric_objects::{MovingSphere, Sphere, Rect},
-    materials::{Material, Texture},
+    csg::{Csg, CsgOperation},
+    environment::EnvironmentLight,
+    geometric_objects::{Cone, Cylinder, Disk, GeometricObject, MovingSphere, Sphere, Rect, Torus},
+    heightfield::Heightfield,
+    image::{Image, WrapMode},
+    layered::Coat,
+    lights::Light,
+    materials::{Material, Texture, TextureSpace, TextureTransform},
+    media::{DensityField, DensityGrid, HeterogeneousMedium},
+    noise::{NoiseBasis, Perlin},
+    principled::PrincipledBsdf,
+    procedural::{CellularFeature, ColorRamp, Fbm},
+    sdf::{Sdf, SdfObject},
+    sky::Sky,
+    spectrum::RefractiveIndex,
+    thin_film::ThinFilm,
+    surface_detail::{DetailedSurface, SurfaceDetail},
     world::World,
 };
 
+// Additions to the rendered scene configured from JS, they are kept between renders.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct SceneSettings {
+    texture: Option<Image>,
+    normal_map: Option<Image>,
+    terrain: Option<(usize, usize, Vec<u8>)>,
+    environment: Option<Image>,
+    environment_rotation: f32,
+    lights: Vec<Light>,
+    sky: Option<(f32, f32, f32)>,
+    fog: Option<(f32, f32)>,
+    density_grid: Option<(DensityGrid, f32)>,
+}
+
+#[wasm_bindgen]
+impl SceneSettings {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        SceneSettings::default()
+    }
+
+    // Decodes PNG, PPM or TGA bytes into the image texture shown in the showcase scene.
+    pub fn set_texture_image(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.texture = Some(Image::decode(bytes).map_err(|e| JsValue::from_str(&e))?);
+        Ok(())
+    }
+
+    // Decodes a tangent space normal map for the floor of the showcase scene.
+    pub fn set_normal_map(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.normal_map = Some(Image::decode_data(bytes).map_err(|e| JsValue::from_str(&e))?);
+        Ok(())
+    }
+
+    // Decodes a height map for the terrain of the shapes scene, heights are read from the
+    // first channel.
+    pub fn set_terrain_image(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let image = Image::decode_data(bytes).map_err(|e| JsValue::from_str(&e))?;
+        if image.width() < 2 || image.height() < 2 {
+            return Err(JsValue::from_str("height map needs at least 2x2 pixels"));
+        }
+        let pixels = (0..image.height())
+            .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+            .map(|(x, y)| (image.pixel(x, y).x.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        self.terrain = Some((image.width(), image.height(), pixels));
+        Ok(())
+    }
+
+    // Decodes an equirectangular Radiance HDR or PFM image that lights every scene.
+    pub fn set_environment_map(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.environment = Some(Image::decode(bytes).map_err(|e| JsValue::from_str(&e))?);
+        Ok(())
+    }
+
+    pub fn clear_environment_map(&mut self) {
+        self.environment = None;
+    }
+
+    // Turns the environment map around the vertical axis, in degrees.
+    pub fn set_environment_rotation(&mut self, rotation: f32) {
+        self.environment_rotation = rotation;
+    }
+
+    pub fn add_point_light(&mut self, x: f32, y: f32, z: f32, r: f32, g: f32, b: f32) {
+        self.lights.push(Light::Point {
+            position: Point3::new(x, y, z),
+            intensity: vec3(r, g, b),
+        });
+    }
+
+    // Spot light at `position` shining along `direction`, both given as [x, y, z] and the
+    // intensity as [r, g, b]. Angles are in degrees.
+    pub fn add_spot_light(
+        &mut self,
+        position: &[f32],
+        direction: &[f32],
+        intensity: &[f32],
+        cone_angle: f32,
+        falloff_start: f32,
+    ) -> Result<(), JsValue> {
+        self.lights.push(Light::Spot {
+            position: Point3::from_vec(vector(position)?),
+            direction: vector(direction)?,
+            intensity: vector(intensity)?,
+            cone_angle,
+            falloff_start,
+        });
+        Ok(())
+    }
+
+    // `direction` is the direction the light travels in.
+    pub fn add_directional_light(&mut self, x: f32, y: f32, z: f32, r: f32, g: f32, b: f32) {
+        self.lights.push(Light::Directional {
+            direction: vec3(x, y, z),
+            irradiance: vec3(r, g, b),
+        });
+    }
+
+    pub fn clear_lights(&mut self) {
+        self.lights.clear();
+    }
+
+    // Daylight sky used when no environment map is loaded, angles are in degrees.
+    pub fn set_sky(&mut self, elevation: f32, azimuth: f32, turbidity: f32) {
+        self.sky = Some((elevation, azimuth, turbidity));
+    }
+
+    pub fn clear_sky(&mut self) {
+        self.sky = None;
+    }
+
+    // Patchy fog covering the ground of the scenes up to `height`, `density` is its peak
+    // extinction coefficient.
+    pub fn set_fog(&mut self, density: f32, height: f32) {
+        self.fog = Some((density, height));
+    }
+
+    pub fn clear_fog(&mut self) {
+        self.fog = None;
+    }
+
+    // Smoke or cloud from voxel data, filling a box above the ground in the middle of the
+    // scenes. `bytes` holds nx * ny * nz little-endian f32 values with x varying fastest, and
+    // `density` scales them to extinction coefficients.
+    pub fn set_density_grid(
+        &mut self,
+        nx: usize,
+        ny: usize,
+        nz: usize,
+        bytes: &[u8],
+        density: f32,
+    ) -> Result<(), JsValue> {
+        let grid =
+            DensityGrid::from_raw_bytes(nx, ny, nz, bytes).map_err(|e| JsValue::from_str(&e))?;
+        self.density_grid = Some((grid, density));
+        Ok(())
+    }
+
+    // Like `set_density_grid`, for text starting with the resolution "nx ny nz" followed by
+    // the values.
+    pub fn set_density_grid_text(&mut self, text: &str, density: f32) -> Result<(), JsValue> {
+        let grid = DensityGrid::from_text(text).map_err(|e| JsValue::from_str(&e))?;
+        self.density_grid = Some((grid, density));
+        Ok(())
+    }
+
+    pub fn clear_density_grid(&mut self) {
+        self.density_grid = None;
+    }
+}
+
+fn vector(components: &[f32]) -> Result<Vector3<f32>, JsValue> {
+    <[f32; 3]>::try_from(components)
+        .map(Vector3::from)
+        .map_err(|_| JsValue::from_str("expected a vector with three components"))
+}
+
+impl SceneSettings {
+    pub fn apply(&self, world: &mut World) {
+        if let Some(image) = &self.environment {
+            world.set_environment(EnvironmentLight::new(image.clone(), self.environment_rotation));
+        }
+        if let Some((elevation, azimuth, turbidity)) = self.sky {
+            world.set_sky(Sky::new(elevation, azimuth, turbidity));
+        }
+        for light in &self.lights {
+            world.add_light(*light);
+        }
+        if let Some((density, height)) = self.fog {
+            world.add_medium(HeterogeneousMedium::new(
+                Aabb::new(Point3::new(-12.0, -0.5, -12.0), Point3::new(12.0, height - 0.5, 12.0)),
+                DensityField::Noise { perlin: Perlin::new(3).with_scale(6.0), octaves: 4 },
+                density,
+                vec3(0.9, 0.9, 0.9),
+                0.3,
+            ));
+        }
+        if let Some((grid, density)) = &self.density_grid {
+            world.add_medium(HeterogeneousMedium::new(
+                Aabb::new(Point3::new(-1.5, -0.5, -1.75), Point3::new(1.5, 1.0, -0.25)),
+                DensityField::Grid(grid.clone()),
+                *density,
+                vec3(0.95, 0.95, 0.95),
+                0.6,
+            ));
+        }
+    }
+}
+
 pub fn get_predefined_scene(canvas_width: u16, canvas_height: u16) -> (Camera, World) {
     let world = cascade! {
         World::new();
@@ -18,6 +225,7 @@ pub fn get_predefined_scene(canvas_width: u16, canvas_height: u16) -> (Camera, W
         Point3::new(0.0, -1000.5, -1.0),
         1000.0,
         Material::Lambertian { texture: Texture::Checkerboard{
+            frequency: 10.0,
             left: Box::new(Texture::Constant{
                 color: Point3::new(0.2, 0.3, 0.1)}),
             right: Box::new(Texture::Constant{
@@ -35,12 +243,12 @@ pub fn get_predefined_scene(canvas_width: u16, canvas_height: u16) -> (Camera, W
     ..add_object(Box::new(Sphere::new(
         Point3::new(1.1, 0.0, -1.0),
         0.5,
-        Material::Dielectric{refractive_index: 1.7}
+        Material::Dielectric{refractive_index: 1.7, thin_film: None}
     )));
     ..add_object(Box::new(Sphere::new(
         Point3::new(-0.95, 0.5, -1.0),
         0.45,
-        Material::Lambertian{ texture: Texture::Noise }
+        Material::Lambertian{ texture: Texture::Noise { perlin: Perlin::new(0) } }
     )));
     ..add_object(Box::new(Sphere::new(
         Point3::new(-1.2, -0.2, -1.0),
@@ -74,21 +282,27 @@ pub fn get_predefined_scene(canvas_width: u16, canvas_height: u16) -> (Camera, W
           Material::DiffuseLight{
               texture: Texture::Constant {
                   color: Point3::new(1.0, 1.0, 1.0)
-              }
+              },
+              strength: 1.0,
+              two_sided: true,
           }
     )));
    ..add_object(Box::new(Rect::new(-0.5, 0.5, -0.5, 0.5, 0.9,
           Material::DiffuseLight{
               texture: Texture::Constant {
                   color: Point3::new(1.0, 1.0, 1.0)
-              }
+              },
+              strength: 1.0,
+              two_sided: true,
           }
     )));
     ..add_object(Box::new(Rect::new(0.7, 1.7, -0.5, 0.5, 0.9,
           Material::DiffuseLight{
               texture: Texture::Constant {
                   color: Point3::new(1.0, 1.0, 1.0)
-              }
+              },
+              strength: 1.0,
+              two_sided: true,
           }
     )));
     // ..add_object(Box::new(Rect::new(-10.0, 10.0, -10.0, 10.0, 0.91,
@@ -121,6 +335,616 @@ pub fn get_predefined_scene(canvas_width: u16, canvas_height: u16) -> (Camera, W
     (camera, world)
 }
 
+// Scene demonstrating the materials that the predefined scene doesn't use, lit like it. An
+// image texture set from JS is laid out on the floor below the spheres.
+pub fn get_showcase_scene(
+    canvas_width: u16,
+    canvas_height: u16,
+    settings: &SceneSettings,
+) -> (Camera, World) {
+    let principled = |bsdf: PrincipledBsdf| Material::Principled { bsdf: Box::new(bsdf) };
+    let mut world = cascade! {
+        World::new();
+        ..add_object(Box::new(Sphere::new(
+            Point3::new(0.0, -1000.5, -1.0),
+            1000.0,
+            Material::Lambertian { texture: Texture::scalar(0.5) },
+        )));
+        // Clear coated plastic.
+        ..add_object(Box::new(Sphere::new(
+            Point3::new(-1.2, -0.2, -1.0),
+            0.3,
+            principled(PrincipledBsdf {
+                roughness: Texture::scalar(0.4),
+                clearcoat: Texture::scalar(1.0),
+                ..PrincipledBsdf::new(Texture::Constant { color: Point3::new(0.9, 0.9, 0.2) })
+            }),
+        )));
+        // Metal with alternately polished and rough patches.
+        ..add_object(Box::new(Sphere::new(
+            Point3::new(-0.4, -0.2, -1.0),
+            0.3,
+            principled(PrincipledBsdf {
+                metallic: Texture::scalar(1.0),
+                roughness: Texture::UvChecker {
+                    frequency_u: 8.0,
+                    frequency_v: 4.0,
+                    odd: Box::new(Texture::scalar(0.15)),
+                    even: Box::new(Texture::scalar(0.6)),
+                },
+                ..PrincipledBsdf::new(Texture::Constant { color: Point3::new(0.95, 0.64, 0.54) })
+            }),
+        )));
+        // Glass whose index of refraction rises from the bottom to the top.
+        ..add_object(Box::new(Sphere::new(
+            Point3::new(0.4, -0.2, -1.0),
+            0.3,
+            principled(PrincipledBsdf {
+                roughness: Texture::scalar(0.05),
+                transmission: Texture::scalar(1.0),
+                refractive_index: Texture::Gradient {
+                    from: Point3::new(0.0, -0.3, 0.0),
+                    to: Point3::new(0.0, 0.3, 0.0),
+                    ramp: ColorRamp::between(
+                        Point3::new(1.2, 1.2, 1.2),
+                        Point3::new(1.9, 1.9, 1.9),
+                    ),
+                },
+                ..PrincipledBsdf::new(Texture::scalar(1.0))
+            }),
+        )));
+        // Cloth like sheen over a rough diffuse base.
+        ..add_object(Box::new(Sphere::new(
+            Point3::new(1.2, -0.2, -1.0),
+            0.3,
+            principled(PrincipledBsdf {
+                roughness: Texture::scalar(0.9),
+                specular: Texture::scalar(0.2),
+                sheen: Texture::scalar(1.0),
+                ..PrincipledBsdf::new(Texture::Constant { color: Point3::new(0.6, 0.1, 0.15) })
+            }),
+        )));
+        ..add_object(Box::new(Rect::new(
+            -1.7,
+            1.7,
+            -1.5,
+            -0.5,
+            0.9,
+            Material::DiffuseLight {
+                texture: Texture::scalar(1.0),
+                strength: 2.0,
+                two_sided: true,
+            },
+        )));
+    };
+    if settings.texture.is_some() || settings.normal_map.is_some() {
+        let texture = match &settings.texture {
+            Some(image) => Texture::Image {
+                image: image.clone(),
+                wrap: WrapMode::Clamp,
+            },
+            None => Texture::scalar(0.5),
+        };
+        let floor: Box<dyn GeometricObject> = Box::new(Rect::new(
+            -1.7,
+            1.7,
+            -1.6,
+            -0.4,
+            -0.499,
+            Material::Lambertian { texture },
+        ));
+        world.add_object(match &settings.normal_map {
+            Some(image) => Box::new(DetailedSurface::new(
+                floor,
+                SurfaceDetail::NormalMap {
+                    image: image.clone(),
+                    strength: 1.0,
+                },
+            )),
+            None => floor,
+        });
+    }
+
+    let look_from = Point3::new(0.0, 0.6, 4.5);
+    let look_at = Point3::new(0.0, -0.2, -1.0);
+    let v_up = vec3(0.0, 1.0, 0.0);
+    let dist_to_focus = (look_from - look_at).magnitude();
+    let aperture = 0.02;
+
+    let camera = Camera::new(
+        &look_from,
+        &look_at,
+        &v_up,
+        25.0,
+        f32::from(canvas_width) / f32::from(canvas_height),
+        aperture,
+        dist_to_focus,
+        0.0,
+        1.0,
+    );
+
+    (camera, world)
+}
+
+// Every kind of primitive in a row, from the left: a cylinder, a cone, a bump mapped torus on
+// a disk, a lens shaped CSG solid with holes and a smoothly blended SDF. A chain of SDF rings
+// lies in front and a Mandelbulb floats behind the terrain, which is generated from noise
+// unless a height map is set.
+pub fn get_shapes_scene(
+    canvas_width: u16,
+    canvas_height: u16,
+    settings: &SceneSettings,
+) -> (Camera, World) {
+    let color = |r, g, b| Material::Lambertian {
+        texture: Texture::Constant { color: Point3::new(r, g, b) },
+    };
+    let mut world = cascade! {
+        World::new();
+        ..add_object(Box::new(Sphere::new(
+            Point3::new(0.0, -1000.5, -1.0),
+            1000.0,
+            Material::Lambertian { texture: Texture::scalar(0.5) },
+        )));
+        ..add_object(Box::new(Cylinder::new(
+            Point3::new(-1.4, -0.5, -1.0),
+            0.2,
+            0.6,
+            true,
+            Material::Metallic { r: 0.8, g: 0.8, b: 0.85 },
+        )));
+        ..add_object(Box::new(Cone::new(
+            Point3::new(-0.75, 0.1, -1.0),
+            0.25,
+            0.6,
+            true,
+            color(0.9, 0.5, 0.1),
+        )));
+        ..add_object(Box::new(Disk::new(
+            Point3::new(-0.05, -0.495, -1.0),
+            vec3(0.0, 1.0, 0.0),
+            0.42,
+            color(0.2, 0.2, 0.2),
+        )));
+        ..add_object(Box::new(DetailedSurface::new(
+            Box::new(Torus::new(Point3::new(-0.05, -0.38, -1.0), 0.24, 0.12, color(0.2, 0.4, 0.8))),
+            SurfaceDetail::Bump {
+                height: Texture::Noise { perlin: Perlin::new(1).with_scale(20.0) },
+                scale: 0.02,
+            },
+        )));
+        ..add_object(Box::new(Csg::new(
+            CsgOperation::Difference,
+            Box::new(Csg::new(
+                CsgOperation::Intersection,
+                Box::new(Sphere::new(Point3::new(0.45, -0.2, -1.0), 0.3, color(0.8, 0.15, 0.15))),
+                Box::new(Sphere::new(Point3::new(0.75, -0.2, -1.0), 0.3, color(0.8, 0.15, 0.15))),
+            )),
+            Box::new(Csg::new(
+                CsgOperation::Union,
+                Box::new(Cylinder::new(
+                    Point3::new(0.6, -0.6, -1.0),
+                    0.06,
+                    0.8,
+                    true,
+                    color(0.9, 0.9, 0.9),
+                )),
+                Box::new(Sphere::new(Point3::new(0.6, -0.2, -0.75), 0.1, color(0.9, 0.9, 0.9))),
+            )),
+        )));
+        ..add_object(Box::new(SdfObject::new(
+            Sdf::Translate {
+                offset: vec3(1.35, -0.28, -1.0),
+                sdf: Box::new(Sdf::SmoothSubtraction {
+                    left: Box::new(Sdf::SmoothUnion {
+                        left: Box::new(Sdf::RoundedBox {
+                            half_extents: vec3(0.2, 0.2, 0.2),
+                            radius: 0.04,
+                        }),
+                        right: Box::new(Sdf::Capsule {
+                            a: Point3::new(0.0, 0.0, 0.0),
+                            b: Point3::new(0.0, 0.35, 0.0),
+                            radius: 0.07,
+                        }),
+                        smoothness: 0.08,
+                    }),
+                    right: Box::new(Sdf::Sphere { radius: 0.25 }),
+                    smoothness: 0.03,
+                }),
+            },
+            Aabb::new(Point3::new(1.1, -0.5, -1.25), Point3::new(1.6, 0.2, -0.75)),
+            color(0.3, 0.7, 0.3),
+        )));
+        ..add_object(Box::new(SdfObject::new(
+            Sdf::Translate {
+                offset: vec3(0.0, -0.475, -0.2),
+                sdf: Box::new(Sdf::Repeat {
+                    period: vec3(0.2, 0.0, 0.0),
+                    sdf: Box::new(Sdf::Torus { major_radius: 0.07, minor_radius: 0.02 }),
+                }),
+            },
+            Aabb::new(Point3::new(-1.6, -0.5, -0.3), Point3::new(1.6, -0.45, -0.1)),
+            Material::Metallic { r: 0.9, g: 0.75, b: 0.4 },
+        )));
+        ..add_object(Box::new(SdfObject::new(
+            Sdf::Translate {
+                offset: vec3(0.0, 0.9, -7.0),
+                sdf: Box::new(Sdf::Mandelbulb { power: 8.0, iterations: 6 }),
+            },
+            Aabb::new(Point3::new(-1.2, -0.3, -8.2), Point3::new(1.2, 2.1, -5.8)),
+            color(0.7, 0.6, 0.8),
+        )));
+        ..add_object(Box::new(Rect::new(
+            -1.7,
+            1.7,
+            -1.5,
+            -0.5,
+            0.9,
+            Material::DiffuseLight {
+                texture: Texture::scalar(1.0),
+                strength: 2.0,
+                two_sided: true,
+            },
+        )));
+    };
+    let (origin, extent) = (Point3::new(-3.0, -0.5, -5.0), vec3(6.0, 0.8, 2.5));
+    let terrain = match &settings.terrain {
+        Some((width, height, pixels)) => {
+            let material = color(0.4, 0.5, 0.3);
+            Heightfield::from_grayscale(origin, extent, *width, *height, pixels, material)
+        }
+        None => Heightfield::from_noise(
+            origin,
+            extent,
+            48,
+            &Perlin::new(5).with_scale(3.0),
+            5,
+            color(0.4, 0.5, 0.3),
+        ),
+    };
+    world.add_object(Box::new(terrain.expect("height maps are checked when they are set")));
+
+    let look_from = Point3::new(0.0, 0.6, 4.5);
+    let look_at = Point3::new(0.0, -0.2, -1.0);
+    let v_up = vec3(0.0, 1.0, 0.0);
+    let dist_to_focus = (look_from - look_at).magnitude();
+    let aperture = 0.02;
+
+    let camera = Camera::new(
+        &look_from,
+        &look_at,
+        &v_up,
+        25.0,
+        f32::from(canvas_width) / f32::from(canvas_height),
+        aperture,
+        dist_to_focus,
+        0.0,
+        1.0,
+    );
+
+    (camera, world)
+}
+
+// Camera looking down at the rows of spheres of the gallery scenes.
+fn gallery_camera(canvas_width: u16, canvas_height: u16) -> Camera {
+    let look_from = Point3::new(0.0, 1.4, 3.2);
+    let look_at = Point3::new(0.0, -0.35, -1.0);
+    let v_up = vec3(0.0, 1.0, 0.0);
+    let dist_to_focus = (look_from - look_at).magnitude();
+
+    Camera::new(
+        &look_from,
+        &look_at,
+        &v_up,
+        30.0,
+        f32::from(canvas_width) / f32::from(canvas_height),
+        0.0,
+        dist_to_focus,
+        0.0,
+        1.0,
+    )
+}
+
+// Gray ground under the area light shared by the gallery scenes.
+fn gallery_world() -> World {
+    cascade! {
+        World::new();
+        ..add_object(Box::new(Sphere::new(
+            Point3::new(0.0, -1000.5, -1.0),
+            1000.0,
+            Material::Lambertian { texture: Texture::scalar(0.5) },
+        )));
+        ..add_object(Box::new(Rect::new(
+            -2.0,
+            2.0,
+            -2.5,
+            0.5,
+            2.5,
+            Material::DiffuseLight {
+                texture: Texture::scalar(1.0),
+                strength: 3.0,
+                two_sided: true,
+            },
+        )));
+    }
+}
+
+// Center of the sphere in `column` of `row` of a gallery scene, rows are counted from the back.
+fn gallery_slot(column: usize, row: usize) -> Point3<f32> {
+    Point3::new(-1.5 + 0.6 * column as f32, -0.25, -2.1 + 0.7 * row as f32)
+}
+
+// Leaf drawn into an image whose alpha channel is zero outside of the outline. The leaf points
+// along u and its midrib runs along v = 0.5.
+fn leaf_image() -> Image {
+    let size = 64;
+    let pixels = (0..size * size)
+        .map(|i| {
+            let u = (i % size) as f32 / (size - 1) as f32;
+            let v = (i / size) as f32 / (size - 1) as f32;
+            let half_width = 0.4 * (f32::consts::PI * u).sin().powf(0.7);
+            let across = (v - 0.5).abs();
+            let shade = if across < 0.02 { 1.0 } else { 0.6 + 0.3 * across / half_width };
+            let alpha = if across < half_width { 1.0 } else { 0.0 };
+            vec4(0.2 * shade, 0.5 * shade, 0.1 * shade, alpha)
+        })
+        .collect();
+    Image::new(size, size, pixels).expect("the leaf has a pixel for every texel")
+}
+
+// Rows of spheres made of the materials that have no place in the other scenes. The back row
+// holds microfacet conductors and the second row rough and absorbing glass, followed by
+// dispersive diamond, crown glass and fused silica that split light in the spectral mode. The
+// last sphere of both rows is iridescent from thin-film interference. The third row starts with
+// materials under a clear and a tinted coat and metal mixed with rust. Rough Oren-Nayar clay
+// is followed by a Lambertian reference and by light scattering below the surface of a
+// translucent sphere. In front, a leaf is cut out of a quad by an opacity mask and two small
+// lamps glow with blackbody colors.
+pub fn get_material_gallery(canvas_width: u16, canvas_height: u16) -> (Camera, World) {
+    let sphere = |column, row, material| {
+        Box::new(Sphere::new(gallery_slot(column, row), 0.25, material))
+    };
+    let lamp = |column, kelvin| {
+        let center = gallery_slot(column, 3) - vec3(0.0, 0.1, 0.0);
+        Box::new(Sphere::new(center, 0.15, Material::blackbody_light(kelvin, 1.5)))
+    };
+    let clay = || Texture::Constant { color: Point3::new(0.7, 0.45, 0.3) };
+    let leaf = leaf_image();
+    let world = cascade! {
+        gallery_world();
+        ..add_object(Box::new(Rect::new(
+            -1.75,
+            -0.65,
+            -0.3,
+            0.25,
+            -0.15,
+            Material::Masked {
+                opacity: Texture::Alpha { image: leaf.clone(), wrap: WrapMode::Clamp },
+                material: Box::new(Material::Lambertian {
+                    texture: Texture::Image { image: leaf, wrap: WrapMode::Clamp },
+                }),
+            },
+        )));
+        ..add_object(sphere(0, 0, Material::gold(0.15)));
+        ..add_object(sphere(1, 0, Material::copper(0.3)));
+        ..add_object(sphere(2, 0, Material::silver(0.05)));
+        ..add_object(sphere(3, 0, Material::aluminium(0.4)));
+        // Brushed steel, stretched highlights come from the anisotropic roughness.
+        ..add_object(sphere(
+            4,
+            0,
+            Material::conductor(vec3(2.87, 2.92, 2.80), vec3(3.21, 3.01, 2.77), 0.35, 0.8),
+        ));
+        // Anodized aluminium, colored by its oxide layer.
+        ..add_object(sphere(
+            5,
+            0,
+            Material::aluminium(0.1).with_thin_film(ThinFilm::new(300.0, 1.65)),
+        ));
+        // Frosted green glass and clear amber glass, tinted by absorption along the path inside.
+        ..add_object(sphere(0, 1, Material::glass(1.5, 0.25, vec3(0.4, 0.85, 0.5), 0.5)));
+        ..add_object(sphere(1, 1, Material::glass(1.5, 0.0, vec3(0.95, 0.6, 0.2), 0.5)));
+        ..add_object(sphere(2, 1, Material::DispersiveDielectric {
+            refractive_index: RefractiveIndex::diamond(),
+        }));
+        ..add_object(sphere(3, 1, Material::DispersiveDielectric {
+            refractive_index: RefractiveIndex::crown_glass(),
+        }));
+        ..add_object(sphere(4, 1, Material::DispersiveDielectric {
+            refractive_index: RefractiveIndex::fused_silica(),
+        }));
+        // Soap bubble, a film of soapy water with air on both sides.
+        ..add_object(sphere(
+            5,
+            1,
+            Material::Dielectric { refractive_index: 1.0, thin_film: None }
+                .with_thin_film(ThinFilm::new(380.0, 1.33)),
+        ));
+        // Red paint under clear lacquer and copper under a thick amber varnish.
+        ..add_object(sphere(
+            0,
+            2,
+            Material::Lambertian {
+                texture: Texture::Constant { color: Point3::new(0.7, 0.05, 0.05) },
+            }
+            .coated(Coat::clear(0.05)),
+        ));
+        ..add_object(sphere(
+            1,
+            2,
+            Material::copper(0.3).coated(Coat::new(1.55, 0.1, 0.05, vec3(2.0, 6.0, 20.0))),
+        ));
+        // Rusty steel, turbulence picks where the bare metal shows.
+        ..add_object(sphere(
+            2,
+            2,
+            Material::mix(
+                Material::aluminium(0.3),
+                Material::Lambertian {
+                    texture: Texture::Constant { color: Point3::new(0.35, 0.12, 0.04) },
+                },
+                Texture::Turbulence {
+                    fbm: Fbm::new(Perlin::new(21).with_scale(8.0), 5, 2.0, 0.5),
+                    ramp: ColorRamp::new(vec![
+                        (0.15, Point3::new(1.0, 1.0, 1.0)),
+                        (0.25, Point3::new(0.0, 0.0, 0.0)),
+                    ])
+                    .expect("rust ramp stops are finite"),
+                },
+            ),
+        ));
+        // Rough clay next to a Lambertian sphere of the same color, the rough one looks flatter.
+        ..add_object(sphere(3, 2, Material::oren_nayar(clay(), 0.5)));
+        ..add_object(sphere(4, 2, Material::Lambertian { texture: clay() }));
+        // Translucent wax, red light travels the furthest below the surface.
+        ..add_object(sphere(
+            5,
+            2,
+            Material::subsurface(vec3(0.99, 0.95, 0.9), vec3(0.12, 0.05, 0.03)),
+        ));
+        // Small lamps glowing like an incandescent bulb and like daylight.
+        ..add_object(lamp(3, 2700.0));
+        ..add_object(lamp(4, 6500.0));
+    };
+
+    (gallery_camera(canvas_width, canvas_height), world)
+}
+
+// Rows of spheres with procedural textures. The back row holds turbulence, marble, wood and
+// three features of cellular noise, the second row a UV grid, textures combined by multiplying
+// and mixing and a vertical gradient through several colors. The remaining spheres show single
+// octaves of Perlin and simplex noise over the hit point, over the texture coordinates and as a
+// slice of four dimensional noise. The last two apply a rotation in texture coordinates and in
+// object space.
+pub fn get_texture_gallery(canvas_width: u16, canvas_height: u16) -> (Camera, World) {
+    let sphere = |column, row, texture| {
+        Box::new(Sphere::new(gallery_slot(column, row), 0.25, Material::Lambertian { texture }))
+    };
+    let ramp = |stops| ColorRamp::new(stops).expect("gallery ramp stops are finite");
+    let marble = || Texture::Marble {
+        fbm: Fbm::new(Perlin::new(12).with_scale(4.0), 6, 2.0, 0.5),
+        frequency: 20.0,
+        distortion: 8.0,
+        ramp: ramp(vec![
+            (0.0, Point3::new(0.25, 0.25, 0.3)),
+            (0.2, Point3::new(0.7, 0.7, 0.72)),
+            (1.0, Point3::new(0.95, 0.95, 0.93)),
+        ]),
+    };
+    let wood = || Texture::Wood {
+        fbm: Fbm::new(Perlin::new(13).with_scale(6.0), 4, 2.0, 0.5),
+        rings: 40.0,
+        distortion: 0.03,
+        ramp: ramp(vec![
+            (0.0, Point3::new(0.45, 0.25, 0.1)),
+            (0.7, Point3::new(0.7, 0.45, 0.2)),
+            (1.0, Point3::new(0.45, 0.25, 0.1)),
+        ]),
+    };
+    let basis_noise = |basis, scale| Texture::BasisNoise {
+        perlin: Perlin::new(15).with_scale(scale),
+        basis,
+        ramp: ramp(vec![
+            (0.25, Point3::new(0.05, 0.05, 0.1)),
+            (0.75, Point3::new(0.95, 0.9, 0.8)),
+        ]),
+    };
+    let cells = |feature, ramp| Texture::Worley {
+        scale: 12.0,
+        jitter: 1.0,
+        seed: 3,
+        feature,
+        ramp,
+    };
+
+    let world = cascade! {
+        gallery_world();
+        ..add_object(sphere(0, 0, Texture::Turbulence {
+            fbm: Fbm::new(Perlin::new(11).with_scale(6.0), 6, 2.0, 0.5),
+            ramp: ramp(vec![
+                (0.0, Point3::new(0.05, 0.1, 0.4)),
+                (0.4, Point3::new(0.9, 0.9, 0.9)),
+                (1.0, Point3::new(0.9, 0.5, 0.1)),
+            ]),
+        }));
+        ..add_object(sphere(1, 0, marble()));
+        ..add_object(sphere(2, 0, wood()));
+        ..add_object(sphere(3, 0, cells(CellularFeature::Nearest, ColorRamp::grayscale())));
+        ..add_object(sphere(4, 0, cells(
+            CellularFeature::SecondNearest,
+            ColorRamp::between(Point3::new(0.1, 0.3, 0.1), Point3::new(0.8, 0.9, 0.4)),
+        )));
+        // Cracked tiles, the mortar lies where two cells are about equally far.
+        ..add_object(sphere(5, 0, cells(
+            CellularFeature::Edge,
+            ramp(vec![
+                (0.0, Point3::new(0.1, 0.1, 0.1)),
+                (0.08, Point3::new(0.75, 0.35, 0.2)),
+            ]),
+        )));
+        ..add_object(sphere(0, 1, Texture::UvGrid {
+            frequency_u: 12.0,
+            frequency_v: 6.0,
+            line_width: 0.1,
+            line: Box::new(Texture::scalar(0.9)),
+            fill: Box::new(Texture::Constant { color: Point3::new(0.1, 0.2, 0.6) }),
+        }));
+        // Marble darkened by a cellular pattern.
+        ..add_object(sphere(1, 1, Texture::Multiply {
+            a: Box::new(marble()),
+            b: Box::new(cells(
+                CellularFeature::Nearest,
+                ColorRamp::between(Point3::new(0.3, 0.3, 0.3), Point3::new(1.0, 1.0, 1.0)),
+            )),
+        }));
+        // Wood veneer with marble inlays, switching with turbulence.
+        ..add_object(sphere(2, 1, Texture::Mix {
+            a: Box::new(wood()),
+            b: Box::new(marble()),
+            factor: Box::new(Texture::Turbulence {
+                fbm: Fbm::new(Perlin::new(14).with_scale(3.0), 3, 2.0, 0.5),
+                ramp: ramp(vec![
+                    (0.25, Point3::new(0.0, 0.0, 0.0)),
+                    (0.3, Point3::new(1.0, 1.0, 1.0)),
+                ]),
+            }),
+        }));
+        ..add_object(sphere(3, 1, Texture::Gradient {
+            from: Point3::new(0.0, -0.25, 0.0),
+            to: Point3::new(0.0, 0.25, 0.0),
+            ramp: ramp(vec![
+                (0.0, Point3::new(0.9, 0.3, 0.1)),
+                (0.5, Point3::new(0.95, 0.85, 0.3)),
+                (1.0, Point3::new(0.2, 0.4, 0.9)),
+            ]),
+        }));
+        ..add_object(sphere(4, 1, basis_noise(NoiseBasis::Perlin, 12.0)));
+        ..add_object(sphere(5, 1, basis_noise(NoiseBasis::Simplex, 12.0)));
+        ..add_object(sphere(0, 2, basis_noise(NoiseBasis::PerlinUv, 16.0)));
+        ..add_object(sphere(1, 2, basis_noise(NoiseBasis::SimplexUv, 16.0)));
+        ..add_object(sphere(2, 2, basis_noise(NoiseBasis::Perlin4d { w: 0.5 }, 12.0)));
+        // The grid of the second row turned diagonal in texture coordinates.
+        ..add_object(sphere(3, 2, Texture::UvGrid {
+            frequency_u: 12.0,
+            frequency_v: 6.0,
+            line_width: 0.1,
+            line: Box::new(Texture::scalar(0.9)),
+            fill: Box::new(Texture::Constant { color: Point3::new(0.6, 0.1, 0.1) }),
+        }.transformed(
+            TextureSpace::Uv,
+            TextureTransform::new().offset(vec3(-0.5, -0.5, 0.0)).rotated_2d(45.0),
+        )));
+        // Wood with its rings around the z axis instead of y, stretched along it.
+        ..add_object(sphere(4, 2, wood().transformed(
+            TextureSpace::Object,
+            TextureTransform::new()
+                .rotated(vec3(1.0, 0.0, 0.0), 90.0)
+                .scaled(vec3(1.0, 0.4, 1.0)),
+        )));
+    };
+
+    (gallery_camera(canvas_width, canvas_height), world)
+}
+
 pub fn get_random_scene(
     canvas_width: u16,
     canvas_height: u16,
@@ -0,0 +1,263 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Point3, Vector3};
+use std::f32;
+
+use crate::aabb::Aabb;
+use crate::geometric_objects::{azimuthal_tangent, spherical_uv, GeometricObject};
+use crate::materials::Material;
+use crate::shade_record::ShadeRecord;
+use crate::Ray;
+
+const MAX_MARCHING_STEPS: usize = 256;
+const SURFACE_DISTANCE: f32 = 1e-4;
+const GRADIENT_STEP: f32 = 1e-3;
+
+pub enum Sdf {
+    Sphere {
+        radius: f32,
+    },
+    Box {
+        half_extents: Vector3<f32>,
+    },
+    RoundedBox {
+        half_extents: Vector3<f32>,
+        radius: f32,
+    },
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    Capsule {
+        a: Point3<f32>,
+        b: Point3<f32>,
+        radius: f32,
+    },
+    Mandelbulb {
+        power: f32,
+        iterations: u8,
+    },
+    Translate {
+        offset: Vector3<f32>,
+        sdf: Box<Sdf>,
+    },
+    SmoothUnion {
+        left: Box<Sdf>,
+        right: Box<Sdf>,
+        smoothness: f32,
+    },
+    // Carves `right` out of `left`.
+    SmoothSubtraction {
+        left: Box<Sdf>,
+        right: Box<Sdf>,
+        smoothness: f32,
+    },
+    // Infinite repetition of `sdf` with the given cell size, a zero component disables the axis.
+    Repeat {
+        period: Vector3<f32>,
+        sdf: Box<Sdf>,
+    },
+}
+
+#[inline]
+fn repeat_axis(x: f32, period: f32) -> f32 {
+    if period > 0.0 {
+        x - period * (x / period).round()
+    } else {
+        x
+    }
+}
+
+impl Sdf {
+    pub fn distance(&self, p: &Point3<f32>) -> f32 {
+        match self {
+            Sdf::Sphere { radius } => p.to_vec().magnitude() - radius,
+            Sdf::Box { half_extents } => {
+                let q = vec3(p.x.abs(), p.y.abs(), p.z.abs()) - half_extents;
+                vec3(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).magnitude()
+                    + q.x.max(q.y.max(q.z)).min(0.0)
+            }
+            Sdf::RoundedBox {
+                half_extents,
+                radius,
+            } => {
+                let shrunk = half_extents - vec3(*radius, *radius, *radius);
+                Sdf::Box {
+                    half_extents: shrunk,
+                }
+                .distance(p)
+                    - radius
+            }
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let ring = (p.x * p.x + p.z * p.z).sqrt() - major_radius;
+                (ring * ring + p.y * p.y).sqrt() - minor_radius
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let pa = p - a;
+                let ba = b - a;
+                let h = (pa.dot(ba) / ba.magnitude2()).clamp(0.0, 1.0);
+                (pa - ba * h).magnitude() - radius
+            }
+            Sdf::Mandelbulb { power, iterations } => {
+                let c = p.to_vec();
+                let mut z = c;
+                let mut dr = 1.0;
+                let mut r = z.magnitude();
+
+                for _ in 0..*iterations {
+                    if r > 2.0 || r == 0.0 {
+                        break;
+                    }
+                    let theta = (z.z / r).acos() * power;
+                    let phi = z.y.atan2(z.x) * power;
+                    dr = r.powf(power - 1.0) * power * dr + 1.0;
+                    z = r.powf(*power)
+                        * vec3(
+                            theta.sin() * phi.cos(),
+                            theta.sin() * phi.sin(),
+                            theta.cos(),
+                        )
+                        + c;
+                    r = z.magnitude();
+                }
+                if r == 0.0 {
+                    0.0
+                } else {
+                    0.5 * r.ln() * r / dr
+                }
+            }
+            Sdf::Translate { offset, sdf } => sdf.distance(&(p - offset)),
+            Sdf::SmoothUnion {
+                left,
+                right,
+                smoothness,
+            } => {
+                let (d1, d2) = (left.distance(p), right.distance(p));
+                let h = (0.5 + 0.5 * (d2 - d1) / smoothness).clamp(0.0, 1.0);
+                d2 + (d1 - d2) * h - smoothness * h * (1.0 - h)
+            }
+            Sdf::SmoothSubtraction {
+                left,
+                right,
+                smoothness,
+            } => {
+                let (d1, d2) = (left.distance(p), right.distance(p));
+                let h = (0.5 - 0.5 * (d1 + d2) / smoothness).clamp(0.0, 1.0);
+                d1 + (-d2 - d1) * h + smoothness * h * (1.0 - h)
+            }
+            Sdf::Repeat { period, sdf } => sdf.distance(&Point3::new(
+                repeat_axis(p.x, period.x),
+                repeat_axis(p.y, period.y),
+                repeat_axis(p.z, period.z),
+            )),
+        }
+    }
+
+    // Tetrahedral central differences of the distance field.
+    pub fn gradient(&self, p: &Point3<f32>) -> Vector3<f32> {
+        [
+            vec3(1.0, -1.0, -1.0),
+            vec3(-1.0, -1.0, 1.0),
+            vec3(-1.0, 1.0, -1.0),
+            vec3(1.0, 1.0, 1.0),
+        ]
+        .iter()
+        .fold(vec3(0.0, 0.0, 0.0), |acc, k| {
+            acc + k * self.distance(&(p + k * GRADIENT_STEP))
+        })
+    }
+}
+
+// Implicit surface traced by sphere tracing, `bounds` limits the marching interval and is
+// required for unbounded fields such as repeated domains.
+pub struct SdfObject {
+    sdf: Sdf,
+    bounds: Aabb,
+    material: Material,
+}
+
+impl SdfObject {
+    pub const fn new(sdf: Sdf, bounds: Aabb, material: Material) -> Self {
+        SdfObject {
+            sdf,
+            bounds,
+            material,
+        }
+    }
+
+    // Marches to the first surface crossing after `t_start`. With `leave_surface` set, a march
+    // starting on the surface has to leave it before a crossing is accepted, so rays spawned
+    // on the surface don't hit it again and rays going inwards find the exit instead.
+    fn march(&self, ray: &Ray, t_start: f32, t_end: f32, leave_surface: bool) -> Option<f32> {
+        let speed = ray.direction.magnitude();
+        let mut t = t_start;
+        let mut started_outside: Option<bool> = None;
+
+        for _ in 0..MAX_MARCHING_STEPS {
+            if t > t_end {
+                return None;
+            }
+            let distance = self.sdf.distance(&ray.point_at_parameter(t));
+
+            match started_outside {
+                None if distance.abs() > SURFACE_DISTANCE => started_outside = Some(distance > 0.0),
+                None if !leave_surface => return Some(t),
+                Some(outside)
+                    if distance.abs() < SURFACE_DISTANCE || (distance > 0.0) != outside =>
+                {
+                    return Some(t)
+                }
+                _ => {}
+            }
+            t += distance.abs().max(SURFACE_DISTANCE) / speed;
+        }
+        None
+    }
+
+    fn shade_record(&self, ray: &Ray, t: f32) -> ShadeRecord<'_> {
+        let local_hit_point = ray.point_at_parameter(t);
+        let normal = self.sdf.gradient(&local_hit_point).normalize();
+        let (u, v) = spherical_uv(&normal);
+        let tangent = azimuthal_tangent(&normal);
+
+        ShadeRecord {
+            intersect_parameter: t,
+            local_hit_point,
+            // Distance fields are evaluated in world space, which is their object space.
+            object_hit_point: local_hit_point,
+            normal,
+            geometric_normal: normal,
+            tangent,
+            bitangent: normal.cross(tangent),
+            material: &self.material,
+            u,
+            v,
+        }
+    }
+}
+
+impl GeometricObject for SdfObject {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        let (t_enter, t_exit) = self.bounds.hit(ray, t_min, t_max)?;
+        self.march(ray, t_enter, t_exit, t_enter <= t_min)
+            .map(|t| self.shade_record(ray, t))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+
+    fn hits_along_ray(&self, ray: &Ray) -> Vec<ShadeRecord<'_>> {
+        let mut hits = Vec::new();
+        if let Some((t_enter, t_exit)) = self.bounds.hit(ray, -f32::MAX, f32::MAX) {
+            let mut next = self.march(ray, t_enter, t_exit, false);
+            while let Some(t) = next {
+                hits.push(self.shade_record(ray, t));
+                next = self.march(ray, t, t_exit, true);
+            }
+        }
+        hits
+    }
+}
@@ -3,8 +3,18 @@ use cgmath::{Point3, Vector3};
 use crate::materials::Material;
 
 pub struct ShadeRecord<'a> {
+    // Shading normal, possibly perturbed by a normal or bump map.
     pub normal: Vector3<f32>,
+    // Normal of the actual surface, used to keep light from leaking through it.
+    pub geometric_normal: Vector3<f32>,
+    // Directions in which u and v increase, not necessarily orthogonal to the normal.
+    pub tangent: Vector3<f32>,
+    pub bitangent: Vector3<f32>,
     pub local_hit_point: Point3<f32>,
+    // Hit point relative to the object's own origin, so that textures move with the object.
+    pub object_hit_point: Point3<f32>,
     pub material: &'a Material,
     pub intersect_parameter: f32,
+    pub u: f32,
+    pub v: f32,
 }
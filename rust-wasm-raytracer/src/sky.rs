@@ -0,0 +1,174 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+use rand::random;
+use std::f32;
+
+// Scales sky luminance from kcd/m^2 to the radiance range of emissive materials.
+const SKY_SCALE: f32 = 0.05;
+// Irradiance of the unattenuated sun on a surface facing it.
+const SUN_IRRADIANCE: f32 = 5.0;
+const SUN_ANGULAR_RADIUS: f32 = 0.00465;
+
+// Coefficients of the Perez sky luminance distribution.
+struct Perez {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+}
+
+impl Perez {
+    // Relative luminance for a view zenith angle theta and angle gamma to the sun.
+    #[inline]
+    fn evaluate(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let cos_gamma = gamma.cos();
+        (1.0 + self.a * (self.b / cos_theta).exp())
+            * (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+#[inline]
+fn xyz_to_linear_srgb(x: f32, y: f32, z: f32) -> Vector3<f32> {
+    vec3(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+// Preetham daylight model with an explicitly sampled sun disk. Elevation is measured from the
+// horizon and azimuth around the y axis starting at -z, both in degrees.
+pub struct Sky {
+    sun_direction: Vector3<f32>,
+    sun_radiance: Vector3<f32>,
+    sun_cos_angle: f32,
+    perez_luminance: Perez,
+    perez_x: Perez,
+    perez_y: Perez,
+    zenith: Vector3<f32>,
+}
+
+impl Sky {
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> Self {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        let sun_direction = vec3(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        let t = turbidity.max(1.0);
+        let theta_s = f32::consts::FRAC_PI_2 - elevation.max(0.0);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (f32::consts::PI - 2.0 * theta_s);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let (theta2, theta3) = (theta_s * theta_s, theta_s * theta_s * theta_s);
+        let zenith_x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta_s)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta_s + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta_s + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta_s)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta_s + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta_s + 0.26688);
+
+        let perez_luminance = Perez {
+            a: 0.1787 * t - 1.4630,
+            b: -0.3554 * t + 0.4275,
+            c: -0.0227 * t + 5.3251,
+            d: 0.1206 * t - 2.5771,
+            e: -0.0670 * t + 0.3703,
+        };
+        let perez_x = Perez {
+            a: -0.0193 * t - 0.2592,
+            b: -0.0665 * t + 0.0008,
+            c: -0.0004 * t + 0.2125,
+            d: -0.0641 * t - 0.8989,
+            e: -0.0033 * t + 0.0452,
+        };
+        let perez_y = Perez {
+            a: -0.0167 * t - 0.2608,
+            b: -0.0950 * t + 0.0092,
+            c: -0.0079 * t + 0.2102,
+            d: -0.0441 * t - 1.6537,
+            e: -0.0109 * t + 0.0529,
+        };
+
+        // Sun color from Rayleigh and aerosol (Angstrom) extinction along the relative optical
+        // air mass, evaluated at representative wavelengths of the RGB channels in micrometers.
+        let air_mass =
+            1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).max(0.001).powf(-1.253));
+        let beta = 0.04608 * t - 0.04586;
+        let extinction = |wavelength: f32| {
+            let rayleigh = (-air_mass * 0.008735 * wavelength.powf(-4.08)).exp();
+            let aerosol = (-air_mass * beta * wavelength.powf(-1.3)).exp();
+            rayleigh * aerosol
+        };
+        let sun_cos_angle = SUN_ANGULAR_RADIUS.cos();
+        let sun_solid_angle = 2.0 * f32::consts::PI * (1.0 - sun_cos_angle);
+        let sun_radiance = if elevation > 0.0 {
+            vec3(extinction(0.65), extinction(0.57), extinction(0.475))
+                * (SUN_IRRADIANCE / sun_solid_angle)
+        } else {
+            vec3(0.0, 0.0, 0.0)
+        };
+
+        Sky {
+            sun_direction,
+            sun_radiance,
+            sun_cos_angle,
+            perez_luminance,
+            perez_x,
+            perez_y,
+            zenith: vec3(zenith_luminance, zenith_x, zenith_y),
+        }
+    }
+
+    // Sky radiance from `direction`, the sun disk is left out when it was sampled explicitly.
+    pub fn radiance(&self, direction: &Vector3<f32>, include_sun: bool) -> Vector3<f32> {
+        let d = direction.normalize();
+        let cos_sun = d.dot(self.sun_direction).clamp(-1.0, 1.0);
+        if include_sun && cos_sun >= self.sun_cos_angle {
+            return self.sun_radiance;
+        }
+
+        // The model is only defined above the horizon, lower directions reuse the horizon.
+        let cos_theta = d.y.max(0.01);
+        let gamma = cos_sun.acos();
+        let theta_s = self.sun_direction.y.clamp(0.0, 1.0).acos();
+
+        let relative =
+            |perez: &Perez| perez.evaluate(cos_theta, gamma) / perez.evaluate(1.0, theta_s);
+        let luminance = self.zenith.x * relative(&self.perez_luminance) * SKY_SCALE;
+        let x = self.zenith.y * relative(&self.perez_x);
+        let y = self.zenith.z * relative(&self.perez_y);
+        if y <= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+
+        let color = xyz_to_linear_srgb(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        vec3(color.x.max(0.0), color.y.max(0.0), color.z.max(0.0))
+    }
+
+    // Samples a direction uniformly inside the sun cone, returns it with the sun radiance
+    // and the density with respect to solid angle.
+    pub fn sample_sun(&self) -> (Vector3<f32>, Vector3<f32>, f32) {
+        let cos_theta = 1.0 - random::<f32>() * (1.0 - self.sun_cos_angle);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * f32::consts::PI * random::<f32>();
+
+        let w = self.sun_direction;
+        let helper = if w.x.abs() > 0.9 {
+            vec3(0.0, 1.0, 0.0)
+        } else {
+            vec3(1.0, 0.0, 0.0)
+        };
+        let u = helper.cross(w).normalize();
+        let v = w.cross(u);
+        let direction = u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + w * cos_theta;
+
+        (
+            direction,
+            self.sun_radiance,
+            1.0 / (2.0 * f32::consts::PI * (1.0 - self.sun_cos_angle)),
+        )
+    }
+}
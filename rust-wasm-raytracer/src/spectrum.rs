@@ -0,0 +1,211 @@
+use cgmath::{vec3, Matrix3, Vector3};
+use rand::random;
+use std::sync::OnceLock;
+
+pub const WAVELENGTH_MIN: f32 = 380.0;
+pub const WAVELENGTH_MAX: f32 = 720.0;
+const WAVELENGTH_RANGE: f32 = WAVELENGTH_MAX - WAVELENGTH_MIN;
+
+// Wavelength used for dispersive materials outside of the spectral mode, the sodium d-line.
+pub const REFERENCE_WAVELENGTH: f32 = 587.6;
+
+// Piecewise Gaussian with different widths left and right of the peak.
+#[inline]
+fn lobe(wavelength: f32, mean: f32, sigma_left: f32, sigma_right: f32) -> f32 {
+    let sigma = if wavelength < mean {
+        sigma_left
+    } else {
+        sigma_right
+    };
+    let t = (wavelength - mean) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 color matching functions, multi-lobe fit of Wyman, Sloan and Shirley.
+pub fn color_matching(wavelength: f32) -> Vector3<f32> {
+    vec3(
+        1.056 * lobe(wavelength, 599.8, 37.9, 31.0) + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2),
+        0.821 * lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1),
+        1.217 * lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8),
+    )
+}
+
+// Column major, converts CIE XYZ to linear sRGB.
+fn xyz_to_srgb() -> Matrix3<f32> {
+    Matrix3::new(
+        3.2406, -0.9689, 0.0557, -1.5372, 1.8758, -0.2040, -0.4986, 0.0415, 1.0570,
+    )
+}
+
+#[inline]
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Upsamples an RGB triple to its spectrum at one wavelength. The red, green and blue basis
+// functions sum to one everywhere, so gray values map to flat spectra and reflectances in
+// [0, 1] stay in [0, 1]. Saturated colors don't round trip exactly.
+pub fn rgb_to_spectrum(rgb: &Vector3<f32>, wavelength: f32) -> f32 {
+    let blue = 1.0 - smoothstep(470.0, 510.0, wavelength);
+    let red = smoothstep(570.0, 610.0, wavelength);
+    let green = 1.0 - blue - red;
+    rgb.x * red + rgb.y * green + rgb.z * blue
+}
+
+// Linear sRGB response to a flat unit spectrum, the film divides by it so that white stays white.
+fn white_balance() -> Vector3<f32> {
+    static WHITE: OnceLock<Vector3<f32>> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let steps = WAVELENGTH_RANGE as usize;
+        let xyz = (0..steps).fold(vec3(0.0, 0.0, 0.0), |acc, i| {
+            acc + color_matching(WAVELENGTH_MIN + i as f32 + 0.5)
+        });
+        xyz_to_srgb() * (xyz / xyz.y)
+    })
+}
+
+// Planck's law, spectral radiance of a blackbody at `kelvin` up to a constant factor.
+fn planck(wavelength: f32, kelvin: f32) -> f64 {
+    const SECOND_RADIATION_CONSTANT: f64 = 1.438_777e7;
+    let nm = f64::from(wavelength);
+    1.0 / (nm.powi(5) * ((SECOND_RADIATION_CONSTANT / (nm * f64::from(kelvin))).exp() - 1.0))
+}
+
+// Linear sRGB color of a blackbody at `kelvin`, scaled so that the brightest channel is one.
+pub fn blackbody(kelvin: f32) -> Vector3<f32> {
+    let steps = WAVELENGTH_RANGE as usize;
+    let xyz = (0..steps).fold(vec3(0.0, 0.0, 0.0), |acc, i| {
+        let wavelength = WAVELENGTH_MIN + i as f32 + 0.5;
+        acc + color_matching(wavelength) * planck(wavelength, kelvin) as f32
+    });
+    let rgb = xyz_to_srgb() * xyz;
+    let rgb = vec3(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0));
+    let brightest = rgb.x.max(rgb.y).max(rgb.z);
+    if brightest > 0.0 {
+        rgb / brightest
+    } else {
+        rgb
+    }
+}
+
+// Hero wavelength sampling: one uniformly distributed wavelength and two more evenly
+// rotated through the visible range, carried in the components of the radiance vectors.
+#[derive(Clone, Copy)]
+pub struct SampledWavelengths {
+    pub lambda: Vector3<f32>,
+    // Set once a dispersive interface made the path valid for the hero wavelength only.
+    pub hero_only: bool,
+}
+
+impl SampledWavelengths {
+    pub fn sample() -> Self {
+        let hero = random::<f32>() * WAVELENGTH_RANGE;
+        let rotated =
+            |offset: f32| WAVELENGTH_MIN + (hero + offset * WAVELENGTH_RANGE) % WAVELENGTH_RANGE;
+        SampledWavelengths {
+            lambda: vec3(rotated(0.0), rotated(1.0 / 3.0), rotated(2.0 / 3.0)),
+            hero_only: false,
+        }
+    }
+
+    #[inline]
+    pub fn hero(&self) -> f32 {
+        self.lambda.x
+    }
+
+    pub fn upsample(&self, rgb: &Vector3<f32>) -> Vector3<f32> {
+        vec3(
+            rgb_to_spectrum(rgb, self.lambda.x),
+            rgb_to_spectrum(rgb, self.lambda.y),
+            rgb_to_spectrum(rgb, self.lambda.z),
+        )
+    }
+
+    // Keeps only the hero wavelength, which then stands in for all three samples. Returns the
+    // weight of the radiance arriving along the rest of the path, it is applied only where the
+    // path first became valid for the hero wavelength alone.
+    pub fn terminate_secondary(&mut self) -> Vector3<f32> {
+        if self.hero_only {
+            vec3(1.0, 1.0, 1.0)
+        } else {
+            self.hero_only = true;
+            vec3(3.0, 0.0, 0.0)
+        }
+    }
+
+    // Converts the radiance carried at the sampled wavelengths to linear sRGB. Single
+    // wavelengths lie outside of the gamut, their negative components are kept so that they
+    // cancel out when the samples of a pixel are averaged.
+    pub fn to_rgb(self, radiance: &Vector3<f32>) -> Vector3<f32> {
+        let xyz = (color_matching(self.lambda.x) * radiance.x
+            + color_matching(self.lambda.y) * radiance.y
+            + color_matching(self.lambda.z) * radiance.z)
+            / 3.0;
+        let white = white_balance();
+        let rgb = xyz_to_srgb() * (xyz * (WAVELENGTH_RANGE / luminance_integral()));
+        vec3(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+    }
+}
+
+// Integral of the luminance matching function over the sampled range.
+fn luminance_integral() -> f32 {
+    static INTEGRAL: OnceLock<f32> = OnceLock::new();
+    *INTEGRAL.get_or_init(|| {
+        (0..WAVELENGTH_RANGE as usize)
+            .map(|i| color_matching(WAVELENGTH_MIN + i as f32 + 0.5).y)
+            .sum()
+    })
+}
+
+// Wavelength dependent index of refraction, wavelengths are given in nanometers.
+pub enum RefractiveIndex {
+    Constant(f32),
+    // n = a + b / lambda^2 with lambda in micrometers.
+    Cauchy { a: f32, b: f32 },
+    // n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i)) with lambda in micrometers.
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl RefractiveIndex {
+    pub fn at(&self, wavelength: f32) -> f32 {
+        let micrometers2 = (wavelength * 1e-3) * (wavelength * 1e-3);
+        match self {
+            RefractiveIndex::Constant(n) => *n,
+            RefractiveIndex::Cauchy { a, b } => a + b / micrometers2,
+            RefractiveIndex::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c)
+                    .map(|(b, c)| b * micrometers2 / (micrometers2 - c))
+                    .sum::<f32>())
+            .sqrt(),
+        }
+    }
+
+    #[inline]
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, RefractiveIndex::Constant(_))
+    }
+
+    pub fn crown_glass() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [1.039_612, 0.231_792_3, 1.010_469_5],
+            c: [0.006_000_699, 0.020_017_914, 103.560_6],
+        }
+    }
+
+    pub fn fused_silica() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [0.696_166_3, 0.407_942_6, 0.897_479_4],
+            c: [0.004_679_148, 0.013_512_063, 97.934_0],
+        }
+    }
+
+    pub fn diamond() -> Self {
+        RefractiveIndex::Sellmeier {
+            b: [4.3356, 0.3306, 0.0],
+            c: [0.011_236, 0.030_625, 0.0],
+        }
+    }
+}
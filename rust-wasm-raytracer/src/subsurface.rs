@@ -0,0 +1,104 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+use rand::random;
+
+use crate::dielectric_scatter;
+use crate::media::HenyeyGreenstein;
+use crate::ray::Ray;
+use crate::shade_record::ShadeRecord;
+use crate::world::World;
+
+// Walks longer than this are absorbed, they carry almost no energy anyway.
+const MAX_WALK_STEPS: usize = 1024;
+
+// Interior of a subsurface scattering object, with one extinction coefficient, single
+// scattering albedo and phase anisotropy per component, either color channels or wavelengths.
+pub struct ScatteringMedium {
+    pub extinction: Vector3<f32>,
+    pub albedo: Vector3<f32>,
+    pub anisotropy: Vector3<f32>,
+}
+
+impl ScatteringMedium {
+    pub fn from_mean_free_path(
+        mean_free_path: &Vector3<f32>,
+        albedo: &Vector3<f32>,
+        anisotropy: &Vector3<f32>,
+    ) -> Self {
+        ScatteringMedium {
+            extinction: mean_free_path.map(|length| 1.0 / length.max(1e-6)),
+            albedo: albedo.map(|a| a.clamp(0.0, 1.0)),
+            anisotropy: *anisotropy,
+        }
+    }
+
+    // Density of a collision after travelling `t`, per component.
+    fn collision_density(&self, t: f32) -> Vector3<f32> {
+        self.extinction.map(|sigma| sigma * (-sigma * t).exp())
+    }
+
+    fn transmittance(&self, t: f32) -> Vector3<f32> {
+        self.extinction.map(|sigma| (-sigma * t).exp())
+    }
+}
+
+// Follows a ray refracted into a closed object through its interior until it crosses the
+// boundary again, as found by tracing the world from inside, like refracted dielectric rays.
+// Returns the exit with its normal facing outwards and the throughput of the walk.
+pub fn random_walk<'a>(
+    world: &'a World,
+    entry: &Ray,
+    medium: &ScatteringMedium,
+    refractive_index: f32,
+) -> Option<(ShadeRecord<'a>, Vector3<f32>)> {
+    // The whole walk is sampled with the coefficients of one component. The densities of the
+    // path under every component are tracked relative to each other, and the throughput is the
+    // path contribution over their average, so strongly differing channels stay unbiased.
+    let channel = (random::<f32>() * 3.0) as usize % 3;
+    let extinction = medium.extinction[channel];
+    let phases = medium.anisotropy.map(HenyeyGreenstein::new);
+
+    let mut ray = Ray::new(entry.origin, entry.direction.normalize(), entry.time);
+    let mut path_density = vec3(1.0, 1.0, 1.0);
+    let mut scattering_albedo = vec3(1.0, 1.0, 1.0);
+
+    for _ in 0..MAX_WALK_STEPS {
+        let mut rec = world.trace(&ray)?;
+        let t = -(1.0 - random::<f32>()).ln() / extinction;
+
+        if t < rec.intersect_parameter {
+            let direction = phases[channel].sample(&ray.direction);
+            let cos_theta = direction.dot(ray.direction);
+            let phase = phases.map(|phase| phase.evaluate(cos_theta));
+            path_density = path_density
+                .mul_element_wise(medium.collision_density(t))
+                .mul_element_wise(phase);
+            scattering_albedo = scattering_albedo.mul_element_wise(medium.albedo);
+            path_density /= path_density.x.max(path_density.y).max(path_density.z);
+            ray = Ray::new(ray.point_at_parameter(t), direction, ray.time);
+            continue;
+        }
+
+        path_density = path_density.mul_element_wise(medium.transmittance(rec.intersect_parameter));
+        path_density /= path_density.x.max(path_density.y).max(path_density.z);
+
+        let (reflected, _, reflect_prob) = dielectric_scatter(&ray, &rec.normal, refractive_index);
+        if random::<f32>() < reflect_prob {
+            ray = Ray::new(rec.local_hit_point, reflected.normalize(), ray.time);
+            continue;
+        }
+
+        if ray.direction.dot(rec.normal) < 0.0 {
+            rec.normal = -rec.normal;
+        }
+        if ray.direction.dot(rec.geometric_normal) < 0.0 {
+            rec.geometric_normal = -rec.geometric_normal;
+        }
+        let average = (path_density.x + path_density.y + path_density.z) / 3.0;
+        return Some((
+            rec,
+            path_density.mul_element_wise(scattering_albedo) / average,
+        ));
+    }
+    None
+}
@@ -0,0 +1,146 @@
+use cgmath::prelude::*;
+use cgmath::{vec3, Vector3};
+
+use crate::aabb::Aabb;
+use crate::geometric_objects::GeometricObject;
+use crate::image::{Image, WrapMode};
+use crate::materials::Texture;
+use crate::shade_record::ShadeRecord;
+use crate::Ray;
+
+// Step in UV and world space used to differentiate bump map heights.
+const BUMP_DELTA: f32 = 1e-3;
+
+// Smallest cosine between the perturbed normal and the view direction.
+const MIN_VIEW_COSINE: f32 = 1e-2;
+
+pub enum SurfaceDetail {
+    // Tangent space normals stored as RGB, blue points away from the surface. `strength`
+    // scales the tangential part, zero leaves the surface flat.
+    NormalMap { image: Image, strength: f32 },
+    // Heights read from the first channel of any texture, such as `Texture::Noise` or an
+    // image texture, scaled by `scale`.
+    Bump { height: Texture, scale: f32 },
+}
+
+impl SurfaceDetail {
+    fn height(height: &Texture, u: f32, v: f32, point: &cgmath::Point3<f32>) -> f32 {
+        height.value(u, v, point).x
+    }
+
+    // Perturbed normal in world space for the orthonormal frame of the hit.
+    fn shading_normal(
+        &self,
+        rec: &ShadeRecord,
+        tangent: &Vector3<f32>,
+        bitangent: &Vector3<f32>,
+    ) -> Vector3<f32> {
+        match self {
+            SurfaceDetail::NormalMap { image, strength } => {
+                let texel = image.sample(rec.u, rec.v, WrapMode::Repeat);
+                let local = vec3(
+                    (2.0 * texel.x - 1.0) * strength,
+                    (2.0 * texel.y - 1.0) * strength,
+                    (2.0 * texel.z - 1.0).max(0.0),
+                );
+                tangent * local.x + bitangent * local.y + rec.normal * local.z
+            }
+            SurfaceDetail::Bump { height, scale } => {
+                let point = rec.object_hit_point;
+                let base = SurfaceDetail::height(height, rec.u, rec.v, &point);
+                let along_u = SurfaceDetail::height(
+                    height,
+                    rec.u + BUMP_DELTA,
+                    rec.v,
+                    &(point + tangent * BUMP_DELTA),
+                );
+                let along_v = SurfaceDetail::height(
+                    height,
+                    rec.u,
+                    rec.v + BUMP_DELTA,
+                    &(point + bitangent * BUMP_DELTA),
+                );
+                let du = (along_u - base) / BUMP_DELTA;
+                let dv = (along_v - base) / BUMP_DELTA;
+                rec.normal - (tangent * du + bitangent * dv) * *scale
+            }
+        }
+    }
+}
+
+// Orthonormal tangent frame around the shading normal, keeping the handedness of the
+// surface parametrization.
+fn tangent_frame(rec: &ShadeRecord) -> (Vector3<f32>, Vector3<f32>) {
+    let normal = rec.normal;
+    let mut tangent = rec.tangent - normal * normal.dot(rec.tangent);
+    if tangent.magnitude2() < 1e-12 {
+        let helper = if normal.x.abs() > 0.9 {
+            vec3(0.0, 1.0, 0.0)
+        } else {
+            vec3(1.0, 0.0, 0.0)
+        };
+        tangent = helper.cross(normal);
+    }
+    let tangent = tangent.normalize();
+    let bitangent = normal.cross(tangent);
+    if bitangent.dot(rec.bitangent) < 0.0 {
+        (tangent, -bitangent)
+    } else {
+        (tangent, bitangent)
+    }
+}
+
+// Wraps any primitive and perturbs the shading normal of its hits with a normal or bump map.
+pub struct DetailedSurface {
+    object: Box<dyn GeometricObject>,
+    detail: SurfaceDetail,
+}
+
+impl DetailedSurface {
+    pub fn new(object: Box<dyn GeometricObject>, detail: SurfaceDetail) -> Self {
+        DetailedSurface { object, detail }
+    }
+
+    fn apply<'a>(&self, ray: &Ray, mut rec: ShadeRecord<'a>) -> ShadeRecord<'a> {
+        let (tangent, bitangent) = tangent_frame(&rec);
+        let mut normal = self.detail.shading_normal(&rec, &tangent, &bitangent);
+        if normal.magnitude2() < 1e-12 {
+            return rec;
+        }
+        normal = normal.normalize();
+
+        // The perturbed normal must face the viewer from the same side as the real surface,
+        // otherwise reflections and lighting would come from below the geometry.
+        let view = -ray.direction.normalize();
+        let side = rec.geometric_normal.dot(view).signum();
+        let cosine = normal.dot(view) * side;
+        if cosine < MIN_VIEW_COSINE {
+            normal = (normal + view * ((MIN_VIEW_COSINE - cosine) * side)).normalize();
+        }
+
+        rec.normal = normal;
+        rec.tangent = tangent;
+        rec.bitangent = bitangent;
+        rec
+    }
+}
+
+impl GeometricObject for DetailedSurface {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<ShadeRecord<'_>> {
+        self.object
+            .hit(ray, t_min, t_max)
+            .map(|rec| self.apply(ray, rec))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.object.bounding_box()
+    }
+
+    fn hits_along_ray(&self, ray: &Ray) -> Vec<ShadeRecord<'_>> {
+        self.object
+            .hits_along_ray(ray)
+            .into_iter()
+            .map(|rec| self.apply(ray, rec))
+            .collect()
+    }
+}
@@ -0,0 +1,159 @@
+use cgmath::{vec3, Vector3};
+use std::f32;
+use std::ops::{Add, Div, Mul, Sub};
+
+// Wavelengths in nanometers standing in for the red, green and blue channels outside of the
+// spectral render mode.
+pub const RGB_WAVELENGTHS: Vector3<f32> = Vector3::new(610.0, 550.0, 465.0);
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    const fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+
+    const fn real(re: f32) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    fn from_phase(phase: f32) -> Self {
+        Complex::new(phase.cos(), phase.sin())
+    }
+
+    fn norm_sqr(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal square root, the real part is never negative.
+    fn sqrt(self) -> Self {
+        let modulus = self.norm_sqr().sqrt();
+        let re = (0.5 * (modulus + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (modulus - self.re)).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.norm_sqr();
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        )
+    }
+}
+
+// Cosine of the angle inside a medium with index `n`, given the invariant n1 * sin(theta1).
+#[inline]
+fn cosine_inside(n: Complex, sin_invariant: f32) -> Complex {
+    let ratio = Complex::real(sin_invariant) / n;
+    (Complex::real(1.0) - ratio * ratio).sqrt()
+}
+
+// Amplitude reflection coefficients of an interface for s and p polarized light.
+#[inline]
+fn amplitudes(n1: Complex, cos1: Complex, n2: Complex, cos2: Complex) -> (Complex, Complex) {
+    let s = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let p = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    (s, p)
+}
+
+// Thin dielectric layer on top of a surface, such as an oil slick, an anti-reflection
+// coating or the wall of a soap bubble.
+#[derive(Clone, Copy)]
+pub struct ThinFilm {
+    // Thickness of the film in nanometers.
+    pub thickness: f32,
+    pub refractive_index: f32,
+}
+
+impl ThinFilm {
+    pub const fn new(thickness: f32, refractive_index: f32) -> Self {
+        ThinFilm {
+            thickness,
+            refractive_index,
+        }
+    }
+
+    // Reflectance of the film at one wavelength for light arriving from a medium with index
+    // `outside`, over a substrate with complex index of refraction eta + i k. The multiple
+    // reflections inside the film are summed with the Airy formula.
+    pub fn reflectance(
+        &self,
+        cos_theta: f32,
+        outside: f32,
+        eta: f32,
+        k: f32,
+        wavelength: f32,
+    ) -> f32 {
+        let cos1 = cos_theta.clamp(0.0, 1.0);
+        let sin_invariant = outside * (1.0 - cos1 * cos1).sqrt();
+
+        let n1 = Complex::real(outside);
+        let n2 = Complex::real(self.refractive_index);
+        let n3 = Complex::new(eta, k);
+        let cos1 = Complex::real(cos1);
+        let cos2 = cosine_inside(n2, sin_invariant);
+        let cos3 = cosine_inside(n3, sin_invariant);
+
+        let (r12_s, r12_p) = amplitudes(n1, cos1, n2, cos2);
+        let (r23_s, r23_p) = amplitudes(n2, cos2, n3, cos3);
+
+        // Phase difference of one round trip through the film, complex under total internal
+        // reflection at the top interface where the wave decays inside the film.
+        let phase = Complex::real(4.0 * f32::consts::PI * self.thickness / wavelength) * n2 * cos2;
+        let attenuation = (-phase.im).exp();
+        let round_trip = Complex::from_phase(phase.re) * Complex::real(attenuation);
+
+        let airy = |r12: Complex, r23: Complex| {
+            ((r12 + r23 * round_trip) / (Complex::real(1.0) + r12 * r23 * round_trip)).norm_sqr()
+        };
+        (0.5 * (airy(r12_s, r23_s) + airy(r12_p, r23_p))).clamp(0.0, 1.0)
+    }
+
+    // Reflectance at three wavelengths, one per component of the substrate constants.
+    pub fn reflectance3(
+        &self,
+        cos_theta: f32,
+        outside: f32,
+        eta: &Vector3<f32>,
+        k: &Vector3<f32>,
+        wavelengths: &Vector3<f32>,
+    ) -> Vector3<f32> {
+        vec3(
+            self.reflectance(cos_theta, outside, eta.x, k.x, wavelengths.x),
+            self.reflectance(cos_theta, outside, eta.y, k.y, wavelengths.y),
+            self.reflectance(cos_theta, outside, eta.z, k.z, wavelengths.z),
+        )
+    }
+}
@@ -1,17 +1,41 @@
 
+use rand::random;
 
-use crate::{geometric_objects::GeometricObject, ray::Ray, shade_record::ShadeRecord};
+use crate::{
+    environment::EnvironmentLight,
+    geometric_objects::GeometricObject,
+    lights::Light,
+    materials::Material,
+    media::{HeterogeneousMedium, MediumInteraction},
+    ray::Ray,
+    shade_record::ShadeRecord,
+    sky::Sky,
+};
 
 pub struct World {
     t_min: f32,
     t_max: f32,
     objects: Vec<Box<dyn GeometricObject>>,
+    media: Vec<HeterogeneousMedium>,
+    lights: Vec<Light>,
+    environment: Option<EnvironmentLight>,
+    sky: Option<Sky>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
         World {
             objects: Vec::new(),
+            media: Vec::new(),
+            lights: Vec::new(),
+            environment: None,
+            sky: None,
             t_min: 0.001,
             t_max: f32::MAX
         }
@@ -22,16 +46,118 @@ impl World {
         self.objects.push(object);
     }
 
+    #[inline]
+    pub fn add_medium(&mut self, medium: HeterogeneousMedium) {
+        self.media.push(medium);
+    }
+
+    #[inline]
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    #[inline]
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    #[inline]
+    pub fn set_environment(&mut self, environment: EnvironmentLight) {
+        self.environment = Some(environment);
+    }
+
+    #[inline]
+    pub fn environment(&self) -> Option<&EnvironmentLight> {
+        self.environment.as_ref()
+    }
+
+    #[inline]
+    pub fn set_sky(&mut self, sky: Sky) {
+        self.sky = Some(sky);
+    }
+
+    #[inline]
+    pub fn sky(&self) -> Option<&Sky> {
+        self.sky.as_ref()
+    }
+
+    // Material that shades a hit: mixes pick one of their materials and masks either keep
+    // the material under them or cut the hit away.
+    fn resolve_material<'a>(material: &'a Material, rec: &ShadeRecord) -> Option<&'a Material> {
+        match material {
+            Material::Masked { opacity, material } => {
+                let alpha = opacity.value(rec.u, rec.v, &rec.object_hit_point).x;
+                if alpha >= 1.0 || (alpha > 0.0 && random::<f32>() < alpha) {
+                    World::resolve_material(material, rec)
+                } else {
+                    None
+                }
+            }
+            Material::Mix { .. } => World::resolve_material(
+                material.pick_mixed(rec.u, rec.v, &rec.object_hit_point),
+                rec,
+            ),
+            _ => Some(material),
+        }
+    }
+
+    // Closest hit of `object` that isn't cut away by an opacity mask, traversal continues
+    // behind transparent hits. Kept hits get their resolved material.
+    fn opaque_hit<'a>(
+        &self,
+        object: &'a dyn GeometricObject,
+        ray: &Ray,
+        t_max: f32,
+    ) -> Option<ShadeRecord<'a>> {
+        let mut t_min = self.t_min;
+        loop {
+            let mut rec = object.hit(ray, t_min, t_max)?;
+            if let Some(material) = World::resolve_material(rec.material, &rec) {
+                rec.material = material;
+                return Some(rec);
+            }
+            t_min = rec.intersect_parameter + 1e-4 * rec.intersect_parameter.abs().max(1.0);
+        }
+    }
+
     pub fn trace(&self, ray: &Ray) -> Option<ShadeRecord> {
         let mut shade_record: Option<ShadeRecord> = None;
         let mut closest_so_far = self.t_max;
 
         for object in &self.objects {
-            if let Some(rec) = object.hit(ray, self.t_min, closest_so_far) {
+            if let Some(rec) = self.opaque_hit(object.as_ref(), ray, closest_so_far) {
                 closest_so_far = rec.intersect_parameter;
                 shade_record = Some(rec);
             }
         }
         shade_record
     }
+
+    // Shadow ray query, stops at the first object found closer than `t_max`.
+    pub fn occluded(&self, ray: &Ray, t_max: f32) -> bool {
+        self.objects
+            .iter()
+            .any(|object| self.opaque_hit(object.as_ref(), ray, t_max).is_some())
+    }
+
+    // Each medium is delta tracked independently, the closest real collision wins.
+    pub fn sample_media(&self, ray: &Ray, t_max: f32) -> Option<MediumInteraction<'_>> {
+        let mut interaction: Option<MediumInteraction> = None;
+        let mut closest_so_far = t_max;
+
+        for medium in &self.media {
+            if let Some(event) = medium.sample_interaction(ray, self.t_min, closest_so_far) {
+                closest_so_far = event.intersect_parameter;
+                interaction = Some(event);
+            }
+        }
+        interaction
+    }
+
+    pub fn transmittance(&self, ray: &Ray, t_max: f32) -> f32 {
+        self.media
+            .iter()
+            .map(|medium| medium.transmittance(ray, self.t_min, t_max))
+            .product()
+    }
 }
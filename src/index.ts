@@ -1,4 +1,4 @@
-import init, {make_image} from "../rust-wasm-raytracer/pkg";
+import init, {make_image, RenderMode, SceneSettings, SceneType} from "../rust-wasm-raytracer/pkg";
 
 init().then(() => {
     const renderSettings = document.getElementById('renderSettings') as HTMLElement;
@@ -14,6 +14,42 @@ init().then(() => {
     const samplingSelectButtons = document.getElementsByName(
         'sampler-select'
     ) as NodeListOf<HTMLInputElement>;
+    const renderModeSelectButtons = document.getElementsByName(
+        'render-mode-select'
+    ) as NodeListOf<HTMLInputElement>;
+    const occlusionRadiusInput = document.getElementById(
+        'occlusionRadius'
+    ) as HTMLInputElement;
+    const textureImageInput = document.getElementById(
+        'textureImage'
+    ) as HTMLInputElement;
+    const normalMapInput = document.getElementById(
+        'normalMap'
+    ) as HTMLInputElement;
+    const terrainImageInput = document.getElementById(
+        'terrainImage'
+    ) as HTMLInputElement;
+    const environmentMapInput = document.getElementById(
+        'environmentMap'
+    ) as HTMLInputElement;
+    const environmentRotationInput = document.getElementById(
+        'environmentRotation'
+    ) as HTMLInputElement;
+    const skyEnabledInput = document.getElementById(
+        'skyEnabled'
+    ) as HTMLInputElement;
+    const skyElevationInput = document.getElementById(
+        'skyElevation'
+    ) as HTMLInputElement;
+    const fogDensityInput = document.getElementById(
+        'fogDensity'
+    ) as HTMLInputElement;
+    const densityGridInput = document.getElementById(
+        'densityGrid'
+    ) as HTMLInputElement;
+    const gridDensityInput = document.getElementById(
+        'gridDensity'
+    ) as HTMLInputElement;
     const renderButton = document.getElementById(
         'renderButton'
     ) as HTMLButtonElement;
@@ -41,6 +77,7 @@ init().then(() => {
 
     let sceneType = 'predefined-scene';
     let samplingType = 'jittered-sampling';
+    let renderModeType = 'path-tracing';
     let width = canvas.width;
     let height = canvas.height;
     let numberOfSamples = parseInt(samplesLabel.innerText, 10);
@@ -49,6 +86,71 @@ init().then(() => {
 
     let preventRenderRequests = false;
 
+    const sceneSettings = new SceneSettings();
+
+    // Decoding errors are thrown by the wasm module with a description of the problem.
+    const loadFile = async (input: HTMLInputElement, load: (bytes: Uint8Array) => void) => {
+        const file = input.files?.[0];
+        if (!file) {
+            return;
+        }
+        try {
+            load(new Uint8Array(await file.arrayBuffer()));
+        } catch (error) {
+            alert(`Could not load ${file.name}: ${error}`);
+            input.value = '';
+        }
+    };
+
+    textureImageInput.addEventListener('change', () => {
+        loadFile(textureImageInput, bytes => sceneSettings.set_texture_image(bytes));
+    });
+
+    normalMapInput.addEventListener('change', () => {
+        loadFile(normalMapInput, bytes => sceneSettings.set_normal_map(bytes));
+    });
+
+    terrainImageInput.addEventListener('change', () => {
+        loadFile(terrainImageInput, bytes => sceneSettings.set_terrain_image(bytes));
+    });
+
+    environmentMapInput.addEventListener('change', () => {
+        loadFile(environmentMapInput, bytes => sceneSettings.set_environment_map(bytes));
+    });
+
+    environmentRotationInput.addEventListener('change', () => {
+        sceneSettings.set_environment_rotation(parseFloat(environmentRotationInput.value) || 0);
+    });
+
+    const updateSky = () => {
+        if (skyEnabledInput.checked) {
+            sceneSettings.set_sky(parseFloat(skyElevationInput.value) || 0, 0, 3);
+        } else {
+            sceneSettings.clear_sky();
+        }
+    };
+    skyEnabledInput.addEventListener('change', updateSky);
+    skyElevationInput.addEventListener('change', updateSky);
+
+    fogDensityInput.addEventListener('change', () => {
+        const density = parseFloat(fogDensityInput.value) || 0;
+        if (density > 0) {
+            sceneSettings.set_fog(density, 1);
+        } else {
+            sceneSettings.clear_fog();
+        }
+    });
+
+    // The grid is loaded again when its density changes, the file stays selected in the input.
+    const loadDensityGrid = () => {
+        loadFile(densityGridInput, bytes => sceneSettings.set_density_grid_text(
+            new TextDecoder().decode(bytes),
+            parseFloat(gridDensityInput.value) || 0
+        ));
+    };
+    densityGridInput.addEventListener('change', loadDensityGrid);
+    gridDensityInput.addEventListener('change', loadDensityGrid);
+
     renderButton.addEventListener('click', event => {
         if (preventRenderRequests) {
             return;
@@ -66,11 +168,35 @@ init().then(() => {
             }
         });
 
+        renderModeSelectButtons.forEach(radioButton => {
+            if (radioButton.checked) {
+                renderModeType = radioButton.id;
+            }
+        });
+
         width = canvas.width;
         height = canvas.height;
         numberOfSamples = parseInt(samplesLabel.innerText, 10);
-        const isRandomScene = sceneType !== 'predefined-scene';
+        const sceneTypes: Record<string, SceneType> = {
+            'predefined-scene': SceneType.Predefined,
+            'random-scene': SceneType.Random,
+            'showcase-scene': SceneType.Showcase,
+            'shapes-scene': SceneType.Shapes,
+            'material-gallery': SceneType.Materials,
+            'texture-gallery': SceneType.Textures,
+        };
+        const scene = sceneTypes[sceneType] ?? SceneType.Predefined;
         const isJitteredSampling = samplingType === 'jittered-sampling';
+        const renderModes: Record<string, RenderMode> = {
+            'path-tracing': RenderMode.PathTracing,
+            'spectral': RenderMode.Spectral,
+            'whitted': RenderMode.Whitted,
+            'ambient-occlusion': RenderMode.AmbientOcclusion,
+            'normals': RenderMode.Normals,
+            'albedo': RenderMode.Albedo,
+        };
+        const renderMode = renderModes[renderModeType] ?? RenderMode.PathTracing;
+        const occlusionRadius = parseFloat(occlusionRadiusInput.value) || 0.5;
 
         preventRenderRequests = true;
         renderSettings.style.pointerEvents = 'none';
@@ -85,8 +211,11 @@ init().then(() => {
                 width,
                 height,
                 numberOfSamples,
-                isRandomScene,
-                isJitteredSampling
+                scene,
+                isJitteredSampling,
+                renderMode,
+                occlusionRadius,
+                sceneSettings
             );
 
             console.log("before render");